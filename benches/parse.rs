@@ -0,0 +1,26 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use package_lock_json_parser::{parse, parse_borrowed, PackageLockIndex};
+
+fn bench_parse(c: &mut Criterion) {
+    for file in [
+        "tests/v1/package-lock.json",
+        "tests/cool-project/package-lock.json",
+    ] {
+        let content = std::fs::read_to_string(file).unwrap();
+        let mut group = c.benchmark_group(file);
+        group.bench_function("parse", |b| b.iter(|| parse(black_box(content.as_str()))));
+        group.bench_function("parse_borrowed", |b| {
+            b.iter(|| parse_borrowed(black_box(&content)))
+        });
+        group.bench_function("index_get", |b| {
+            b.iter(|| {
+                let index = PackageLockIndex::new(black_box(content.as_str())).unwrap();
+                index.get("node_modules/@babel/highlight").unwrap()
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
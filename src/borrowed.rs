@@ -0,0 +1,470 @@
+use std::{borrow::Cow, fmt};
+
+use serde::{
+    de::{Error, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use serde_json::value::RawValue;
+use tracing::instrument;
+
+use crate::{engines::split_legacy_entry, LegacyEngines, Map, PackageLockJsonError};
+
+/// Borrowed version of [`crate::PackageLockJson`].
+///
+/// Strings point into the parsed text unless they contain escape sequences.
+/// `packages` is keyed by install path, like [`crate::PackageLockJson::packages_by_path`].
+#[derive(Debug, Deserialize, Default, Clone, Eq, PartialEq)]
+pub struct PackageLockJsonRef<'a> {
+    #[serde(borrow)]
+    pub name: Cow<'a, str>,
+    #[serde(borrow, deserialize_with = "deserialize_str", default)]
+    pub version: Option<Cow<'a, str>>,
+    #[serde(rename = "lockfileVersion")]
+    pub lockfile_version: u32,
+    #[serde(borrow, deserialize_with = "deserialize_map", default)]
+    pub packages: Option<Map<Cow<'a, str>, V2DependencyRef<'a>>>,
+    #[serde(borrow, deserialize_with = "deserialize_map", default)]
+    pub dependencies: Option<Map<Cow<'a, str>, V1DependencyRef<'a>>>,
+}
+
+/// Borrowed version of [`crate::V1Dependency`].
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Default)]
+pub struct V1DependencyRef<'a> {
+    #[serde(borrow)]
+    pub version: Cow<'a, str>,
+    #[serde(borrow, deserialize_with = "deserialize_str", default)]
+    pub resolved: Option<Cow<'a, str>>,
+    #[serde(borrow, deserialize_with = "deserialize_str", default)]
+    pub integrity: Option<Cow<'a, str>>,
+    #[serde(default)]
+    pub bundled: bool,
+    #[serde(rename = "dev", default)]
+    pub is_dev: bool,
+    #[serde(rename = "optional", default)]
+    pub is_optional: bool,
+    #[serde(borrow, deserialize_with = "deserialize_map", default)]
+    pub requires: Option<Map<Cow<'a, str>, Cow<'a, str>>>,
+    #[serde(borrow, deserialize_with = "deserialize_map", default)]
+    pub dependencies: Option<Map<Cow<'a, str>, V1DependencyRef<'a>>>,
+}
+
+/// Borrowed version of [`crate::V2Dependency`].
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Default)]
+pub struct V2DependencyRef<'a> {
+    #[serde(borrow, default)]
+    pub version: Cow<'a, str>,
+    #[serde(borrow, deserialize_with = "deserialize_str", default)]
+    pub name: Option<Cow<'a, str>>,
+    #[serde(borrow, deserialize_with = "deserialize_str", default)]
+    pub resolved: Option<Cow<'a, str>>,
+    #[serde(borrow, deserialize_with = "deserialize_str", default)]
+    pub integrity: Option<Cow<'a, str>>,
+    #[serde(default)]
+    pub bundled: bool,
+    #[serde(rename = "dev", default)]
+    pub is_dev: bool,
+    #[serde(rename = "optional", default)]
+    pub is_optional: bool,
+    #[serde(rename = "devOptional", default)]
+    pub is_dev_optional: bool,
+    #[serde(rename = "inBundle", default)]
+    pub is_in_bundle: bool,
+    #[serde(rename = "hasInstallScript", default)]
+    pub has_install_script: bool,
+    #[serde(rename = "hasShrinkwrap", default)]
+    pub has_shrink_wrap: bool,
+    #[serde(borrow, deserialize_with = "deserialize_map", default)]
+    pub dependencies: Option<Map<Cow<'a, str>, Cow<'a, str>>>,
+    #[serde(
+        rename = "devDependencies",
+        borrow,
+        deserialize_with = "deserialize_map",
+        default
+    )]
+    pub dev_dependencies: Option<Map<Cow<'a, str>, Cow<'a, str>>>,
+    #[serde(
+        rename = "optionalDependencies",
+        borrow,
+        deserialize_with = "deserialize_map",
+        default
+    )]
+    pub optional_dependencies: Option<Map<Cow<'a, str>, Cow<'a, str>>>,
+    #[serde(
+        rename = "peerDependencies",
+        borrow,
+        deserialize_with = "deserialize_map",
+        default
+    )]
+    pub peer_dependencies: Option<Map<Cow<'a, str>, Cow<'a, str>>>,
+    #[serde(borrow, deserialize_with = "deserialize_str", default)]
+    pub license: Option<Cow<'a, str>>,
+    #[serde(borrow, deserialize_with = "deserialize_engines", default)]
+    pub engines: Option<Map<Cow<'a, str>, Cow<'a, str>>>,
+    #[serde(borrow, deserialize_with = "deserialize_map", default)]
+    pub bin: Option<Map<Cow<'a, str>, Cow<'a, str>>>,
+    #[serde(borrow, deserialize_with = "deserialize_str_list", default)]
+    pub workspaces: Option<Vec<Cow<'a, str>>>,
+    #[serde(default)]
+    pub link: bool,
+}
+
+/// Parses a package-lock.json file without copying its strings.
+/// Support v1, v2 and v3 lock files
+///
+/// Unlike [`crate::parse`], a malformed package makes the whole parse fail.
+#[instrument(skip(content))]
+pub fn parse_borrowed(content: &str) -> Result<PackageLockJsonRef<'_>, PackageLockJsonError> {
+    Ok(serde_json::from_str(content)?)
+}
+
+type Engines<'a> = Map<Cow<'a, str>, Cow<'a, str>>;
+
+/// Accepts `engines` as an object or as the legacy forms [`crate::Engines`] repairs,
+/// an array of `"name range"` strings or a single one, with the same rules.
+/// Values that can't be read are skipped with the same warnings.
+/// Ranges are kept as written and not checked.
+fn deserialize_engines<'de, D>(deserializer: D) -> Result<Option<Engines<'de>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct EnginesVisitor;
+
+    impl<'de> EnginesVisitor {
+        fn legacy<E>(
+            self,
+            entry: Cow<'de, str>,
+            form: LegacyEngines,
+        ) -> Result<Option<Engines<'de>>, E> {
+            tracing::warn!(
+                "Found engines as {:?} instead of an object. Fixing it.",
+                form
+            );
+            let mut engines = Map::new();
+            insert_legacy(&mut engines, entry);
+            Ok(Some(engines))
+        }
+
+        fn invalid<E>(self, value: impl fmt::Display) -> Result<Option<Engines<'de>>, E> {
+            tracing::warn!("Invalid engines: {}", value);
+            Ok(None)
+        }
+    }
+
+    impl<'de> Visitor<'de> for EnginesVisitor {
+        type Value = Option<Engines<'de>>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("an object or an array of engines")
+        }
+
+        fn visit_unit<E>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_bool<E>(self, value: bool) -> Result<Self::Value, E> {
+            self.invalid(value)
+        }
+
+        fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E> {
+            self.invalid(value)
+        }
+
+        fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E> {
+            self.invalid(value)
+        }
+
+        fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E> {
+            self.invalid(value)
+        }
+
+        fn visit_borrowed_str<E>(self, entry: &'de str) -> Result<Self::Value, E> {
+            self.legacy(Cow::Borrowed(entry), LegacyEngines::String)
+        }
+
+        fn visit_str<E>(self, entry: &str) -> Result<Self::Value, E> {
+            self.legacy(Cow::Owned(entry.to_string()), LegacyEngines::String)
+        }
+
+        fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: MapAccess<'de>,
+        {
+            let mut engines = Map::new();
+            while let Some((name, range)) = map.next_entry::<BorrowedStr, &RawValue>()? {
+                match borrowed_str(range).map_err(A::Error::custom)? {
+                    Some(range) => {
+                        engines.insert(name.0, range);
+                    }
+                    None => tracing::warn!("Invalid range for engine {}: {}", name.0, range),
+                }
+            }
+            Ok(Some(engines))
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            tracing::warn!(
+                "Found engines as {:?} instead of an object. Fixing it.",
+                LegacyEngines::Array
+            );
+            let mut engines = Map::new();
+            while let Some(entry) = seq.next_element::<&RawValue>()? {
+                match borrowed_str(entry).map_err(A::Error::custom)? {
+                    Some(entry) => insert_legacy(&mut engines, entry),
+                    None => tracing::warn!("Invalid engine entry: {}", entry),
+                }
+            }
+            Ok((!engines.is_empty()).then_some(engines))
+        }
+    }
+
+    deserializer.deserialize_any(EnginesVisitor)
+}
+
+/// Adds a legacy `"name range"` entry, borrowing from it when it is borrowed.
+fn insert_legacy<'a>(engines: &mut Engines<'a>, entry: Cow<'a, str>) {
+    let split = match &entry {
+        Cow::Borrowed(entry) => split_legacy_entry(entry)
+            .map(|(name, range)| (Cow::Borrowed(name), Cow::Borrowed(range))),
+        Cow::Owned(entry) => split_legacy_entry(entry)
+            .map(|(name, range)| (Cow::Owned(name.to_string()), Cow::Owned(range.to_string()))),
+    };
+    match split {
+        Some((name, range)) => {
+            engines.insert(name, range);
+        }
+        None => tracing::warn!("Invalid engine entry: {}", entry.trim()),
+    }
+}
+
+/// Reads a json value as a string, `None` if it is something else.
+fn borrowed_str(value: &RawValue) -> Result<Option<Cow<'_, str>>, serde_json::Error> {
+    if !value.get().starts_with('"') {
+        return Ok(None);
+    }
+    serde_json::from_str::<BorrowedStr>(value.get()).map(|value| Some(value.0))
+}
+
+/// `Cow<str>` only borrows when it is the type of a field marked with
+/// `#[serde(borrow)]`, not when it's nested in an `Option`, a `Vec` or a map.
+struct BorrowedStr<'a>(Cow<'a, str>);
+
+impl<'de> Deserialize<'de> for BorrowedStr<'de> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Wrapper<'a>(#[serde(borrow)] Cow<'a, str>);
+
+        Wrapper::deserialize(deserializer).map(|wrapper| Self(wrapper.0))
+    }
+}
+
+fn deserialize_str<'de, D>(deserializer: D) -> Result<Option<Cow<'de, str>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<BorrowedStr>::deserialize(deserializer)?.map(|value| value.0))
+}
+
+fn deserialize_str_list<'de, D>(deserializer: D) -> Result<Option<Vec<Cow<'de, str>>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let list = Option::<Vec<BorrowedStr>>::deserialize(deserializer)?;
+    Ok(list.map(|list| list.into_iter().map(|value| value.0).collect()))
+}
+
+/// Values of the maps deserialized by [`deserialize_map`].
+trait MapValue<'de>: Sized {
+    fn next_value<A>(map: &mut A) -> Result<Self, A::Error>
+    where
+        A: MapAccess<'de>;
+}
+
+impl<'de> MapValue<'de> for Cow<'de, str> {
+    fn next_value<A>(map: &mut A) -> Result<Self, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        Ok(map.next_value::<BorrowedStr>()?.0)
+    }
+}
+
+impl<'de> MapValue<'de> for V1DependencyRef<'de> {
+    fn next_value<A>(map: &mut A) -> Result<Self, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        map.next_value()
+    }
+}
+
+impl<'de> MapValue<'de> for V2DependencyRef<'de> {
+    fn next_value<A>(map: &mut A) -> Result<Self, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        map.next_value()
+    }
+}
+
+fn deserialize_map<'de, D, V>(deserializer: D) -> Result<Option<Map<Cow<'de, str>, V>>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: MapValue<'de>,
+{
+    struct MapVisitor<V>(std::marker::PhantomData<V>);
+
+    impl<'de, V> Visitor<'de> for MapVisitor<V>
+    where
+        V: MapValue<'de>,
+    {
+        type Value = Option<Map<Cow<'de, str>, V>>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("an object")
+        }
+
+        fn visit_unit<E>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_none<E>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            deserializer.deserialize_map(self)
+        }
+
+        fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: MapAccess<'de>,
+        {
+            let mut values = Map::with_capacity(map.size_hint().unwrap_or_default());
+            while let Some(key) = map.next_key::<BorrowedStr>()? {
+                values.insert(key.0, V::next_value(&mut map)?);
+            }
+            Ok(Some(values))
+        }
+    }
+
+    deserializer.deserialize_option(MapVisitor(std::marker::PhantomData))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn parse_borrowed_works() {
+        let content = std::fs::read_to_string("tests/v3/package-lock.json").unwrap();
+        let lock_file = parse_borrowed(&content).unwrap();
+        assert_eq!(lock_file.name, "cxtl");
+        assert_eq!(lock_file.lockfile_version, 3);
+
+        let packages = lock_file.packages.as_ref().unwrap();
+        let babel_highlight = packages.get("node_modules/@babel/highlight").unwrap();
+        assert_eq!(babel_highlight.version, "7.18.6");
+        assert!(matches!(babel_highlight.version, Cow::Borrowed(_)));
+        assert!(matches!(babel_highlight.resolved, Some(Cow::Borrowed(_))));
+        let (name, range) = babel_highlight
+            .dependencies
+            .as_ref()
+            .unwrap()
+            .iter()
+            .next()
+            .unwrap();
+        assert!(matches!(
+            (name, range),
+            (Cow::Borrowed(_), Cow::Borrowed(_))
+        ));
+        assert!(babel_highlight.is_dev);
+
+        // same packages as the owned parser.
+        let owned = parse(content.as_str()).unwrap();
+        assert_eq!(packages.len(), owned.packages_by_path.unwrap().len());
+    }
+
+    #[test]
+    fn parse_borrowed_fixes_engines() {
+        let content = r#"{
+            "name": "engines",
+            "lockfileVersion": 3,
+            "packages": {
+                "node_modules/extsprintf": {
+                    "version": "1.3.0",
+                    "engines": ["node >=0.6.0"]
+                },
+                "node_modules/empty": {
+                    "version": "1.0.0",
+                    "engines": []
+                },
+                "node_modules/legacy": {
+                    "version": "1.0.0",
+                    "engines": ["node >=0.6.0", "npm>=1.3", 1, "deno"]
+                },
+                "node_modules/string": {
+                    "version": "1.0.0",
+                    "engines": "node >= 0.4"
+                },
+                "node_modules/invalid": {
+                    "version": "1.0.0",
+                    "engines": { "node": ">=18", "npm": 7 }
+                },
+                "node_modules/not-engines": {
+                    "version": "1.0.0",
+                    "engines": true
+                }
+            }
+        }"#;
+        let lock_file = parse_borrowed(content).unwrap();
+        let packages = lock_file.packages.unwrap();
+        let extsprintf = packages.get("node_modules/extsprintf").unwrap();
+        assert_eq!(
+            extsprintf.engines,
+            Some(Map::from([("node".into(), ">=0.6.0".into())]))
+        );
+        assert!(packages
+            .get("node_modules/empty")
+            .unwrap()
+            .engines
+            .is_none());
+        assert!(packages
+            .get("node_modules/not-engines")
+            .unwrap()
+            .engines
+            .is_none());
+
+        // the ranges the owned parser reads.
+        let owned = parse(content).unwrap().packages_by_path.unwrap();
+        for path in [
+            "node_modules/extsprintf",
+            "node_modules/legacy",
+            "node_modules/string",
+            "node_modules/invalid",
+        ] {
+            let engines = packages.get(path).unwrap().engines.as_ref().unwrap();
+            let expected = owned[path]
+                .engines
+                .as_ref()
+                .unwrap()
+                .ranges
+                .iter()
+                .map(|(engine, range)| (Cow::from(engine.as_str()), Cow::from(range.raw.as_str())))
+                .collect::<Map<_, _>>();
+            assert_eq!(*engines, expected, "{path}");
+        }
+        let legacy = packages.get("node_modules/legacy").unwrap();
+        assert_eq!(legacy.engines.as_ref().unwrap()["npm"], ">=1.3");
+    }
+}
@@ -0,0 +1,375 @@
+use crate::{
+    lockfile::v1_root_names,
+    name::{folder_name, is_project_path, split_install_path},
+    normalize_packages, Map, PackageLockJson, PackageLockJsonError, V1Dependency, V2Dependency,
+};
+
+impl PackageLockJson {
+    /// Returns the lock file as lockfileVersion 1.
+    /// The `dependencies` section is rebuilt from `packages` if needed.
+    pub fn to_v1(&self) -> Self {
+        Self {
+            lockfile_version: 1,
+            packages: None,
+            packages_by_path: None,
+            dependencies: Some(self.v1_dependencies()),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the lock file as lockfileVersion 2, with both
+    /// the `packages` and the `dependencies` sections.
+    ///
+    /// v1 files don't record the dependencies of the root project, so the root
+    /// entry of a synthesized `packages` section takes the top level packages
+    /// no other package requires, with their locked version as spec.
+    pub fn to_v2(&self) -> Self {
+        let packages_by_path = self.v2_packages();
+        Self {
+            lockfile_version: 2,
+            packages: Some(normalize_packages(&packages_by_path)),
+            packages_by_path: Some(packages_by_path),
+            dependencies: Some(self.v1_dependencies()),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the lock file as lockfileVersion 3.
+    /// The `packages` section is synthesized from `dependencies` if needed.
+    pub fn to_v3(&self) -> Self {
+        let packages_by_path = self.v2_packages();
+        Self {
+            lockfile_version: 3,
+            packages: Some(normalize_packages(&packages_by_path)),
+            packages_by_path: Some(packages_by_path),
+            dependencies: None,
+            ..self.clone()
+        }
+    }
+
+    /// Serializes the lock file the way npm writes it.
+    pub fn to_json(&self) -> Result<String, PackageLockJsonError> {
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        Ok(json)
+    }
+
+    fn v1_dependencies(&self) -> Map<String, V1Dependency> {
+        match (&self.dependencies, &self.packages_by_path) {
+            (Some(dependencies), _) => dependencies.clone(),
+            (None, Some(packages)) => dependencies_from_packages(packages),
+            (None, None) => Map::new(),
+        }
+    }
+
+    pub(crate) fn v2_packages(&self) -> Map<String, V2Dependency> {
+        match (&self.packages_by_path, &self.dependencies) {
+            (Some(packages), _) => packages.clone(),
+            (None, dependencies) => {
+                let mut root = V2Dependency {
+                    name: Some(self.name.clone()),
+                    version: self.version.clone().unwrap_or_default(),
+                    ..V2Dependency::default()
+                };
+                if let Some(dependencies) = dependencies {
+                    for name in v1_root_names(dependencies) {
+                        let dependency = &dependencies[name];
+                        let section = if dependency.is_dev {
+                            &mut root.dev_dependencies
+                        } else if dependency.is_optional {
+                            &mut root.optional_dependencies
+                        } else {
+                            &mut root.dependencies
+                        };
+                        section
+                            .get_or_insert_with(Map::new)
+                            .insert(name.to_string(), dependency.version.clone());
+                    }
+                }
+                let mut packages = Map::from([(String::new(), root)]);
+                if let Some(dependencies) = dependencies {
+                    add_v1_dependencies(dependencies, "", &mut packages);
+                }
+                packages
+            }
+        }
+    }
+}
+
+/// Adds the v1 dependencies installed in `prefix` to `packages`,
+/// keyed by their install path.
+fn add_v1_dependencies(
+    dependencies: &Map<String, V1Dependency>,
+    prefix: &str,
+    packages: &mut Map<String, V2Dependency>,
+) {
+    for (name, dependency) in dependencies {
+        let path = format!("{prefix}node_modules/{name}");
+        if let Some(folder) = dependency.version.strip_prefix("file:") {
+            // local folders are links in v2.
+            packages.insert(
+                path,
+                V2Dependency {
+                    resolved: Some(folder.to_string()),
+                    link: true,
+                    ..V2Dependency::default()
+                },
+            );
+            packages
+                .entry(folder.to_string())
+                .or_insert_with(|| V2Dependency {
+                    name: Some(name.clone()),
+                    dependencies: dependency.requires.clone(),
+                    ..V2Dependency::default()
+                });
+            if let Some(nested) = &dependency.dependencies {
+                add_v1_dependencies(nested, &format!("{folder}/"), packages);
+            }
+            continue;
+        }
+
+        let mut package = V2Dependency {
+            version: dependency.version.clone(),
+            resolved: dependency.resolved.clone(),
+            integrity: dependency.integrity.clone(),
+            is_in_bundle: dependency.bundled,
+            is_dev: dependency.is_dev,
+            is_optional: dependency.is_optional,
+            dependencies: dependency.requires.clone(),
+            ..V2Dependency::default()
+        };
+        if let Some((real_name, version)) = dependency
+            .version
+            .strip_prefix("npm:")
+            .and_then(|alias| alias.rsplit_once('@'))
+        {
+            // npm:real-name@1.0.0
+            package.name = Some(real_name.to_string());
+            package.version = version.to_string();
+        } else if dependency.version.contains(':') && package.resolved.is_none() {
+            // git and tarball dependencies use the spec as version.
+            package.resolved = Some(dependency.version.clone());
+        }
+        packages.insert(path.clone(), package);
+        if let Some(nested) = &dependency.dependencies {
+            add_v1_dependencies(nested, &format!("{path}/"), packages);
+        }
+    }
+}
+
+/// Rebuilds the nested v1 `dependencies` section out of the install paths.
+fn dependencies_from_packages(packages: &Map<String, V2Dependency>) -> Map<String, V1Dependency> {
+    let links = folder_links(packages);
+
+    // parents first.
+    let mut paths = packages
+        .keys()
+        .filter(|path| !path.is_empty())
+        .collect::<Vec<_>>();
+    paths.sort_by_key(|path| {
+        let depth = split_install_path(path).map_or(0, |(_, names)| names.len());
+        (depth, path.as_str())
+    });
+
+    let mut dependencies = Map::new();
+    for path in paths {
+        let Some(names) = v1_names(&links, path) else {
+            tracing::warn!("No link found for folder {}. Skipping it.", path);
+            continue;
+        };
+        let entry = nested_entry(&mut dependencies, &names);
+        fill_v1_entry(
+            entry,
+            path,
+            &packages[path],
+            names.last().copied().unwrap_or_default(),
+        );
+    }
+    dependencies
+}
+
+/// Returns the name of the link pointing to every folder of the project.
+/// Workspace folders appear in v1 under that name.
+pub(crate) fn folder_links(packages: &Map<String, V2Dependency>) -> Map<&str, &str> {
+    packages
+        .iter()
+        .filter(|(_, package)| package.link)
+        .filter_map(|(path, package)| {
+            let name = folder_name(path)?;
+            Some((package.resolved.as_deref()?, name))
+        })
+        .collect()
+}
+
+/// Returns the chain of names of an install path in the nested v1 `dependencies`,
+/// e.g. `["a", "b"]` for `node_modules/a/node_modules/b`.
+/// `None` if the path is in a folder no link points to.
+pub(crate) fn v1_names<'a>(links: &Map<&str, &'a str>, path: &'a str) -> Option<Vec<&'a str>> {
+    let (folder, mut names) = split_install_path(path)?;
+    if !folder.is_empty() {
+        names.insert(0, links.get(folder)?);
+    }
+    Some(names)
+}
+
+/// Fills the v1 entry of the package installed at `path` under `name`.
+/// Nested dependencies are left as they are.
+pub(crate) fn fill_v1_entry(
+    entry: &mut V1Dependency,
+    path: &str,
+    package: &V2Dependency,
+    name: &str,
+) {
+    if package.link || is_project_path(path) {
+        // a link or the workspace folder it points to.
+        let folder = package
+            .resolved
+            .as_deref()
+            .filter(|_| package.link)
+            .unwrap_or(path);
+        entry.version = format!("file:{folder}");
+        if !package.link {
+            entry.requires = v1_requires(package);
+        }
+        return;
+    }
+
+    entry.version = match &package.name {
+        Some(real_name) if real_name != name => format!("npm:{real_name}@{}", package.version),
+        _ => package.version.clone(),
+    };
+    entry.resolved = package.resolved.clone();
+    if package
+        .resolved
+        .as_ref()
+        .is_some_and(|resolved| resolved.starts_with("git"))
+    {
+        // git dependencies use the spec as version.
+        entry.version = entry.resolved.take().unwrap_or_default();
+    }
+    entry.integrity = package.integrity.clone();
+    entry.bundled = package.is_in_bundle;
+    // v1 has no devOptional, npm marks those entries both dev and optional.
+    entry.is_dev = package.is_dev || package.is_dev_optional;
+    entry.is_optional = package.is_optional || package.is_dev_optional;
+    entry.requires = v1_requires(package);
+}
+
+/// Returns the entry for the given chain of names, creating it if needed.
+pub(crate) fn nested_entry<'a>(
+    dependencies: &'a mut Map<String, V1Dependency>,
+    names: &[&str],
+) -> &'a mut V1Dependency {
+    let (first, rest) = names.split_first().expect("install paths have a name");
+    let entry = dependencies.entry(first.to_string()).or_default();
+    if rest.is_empty() {
+        entry
+    } else {
+        nested_entry(entry.dependencies.get_or_insert_with(Map::new), rest)
+    }
+}
+
+fn v1_requires(package: &V2Dependency) -> Option<Map<String, String>> {
+    let requires = package
+        .dependencies
+        .iter()
+        .chain(package.optional_dependencies.iter())
+        .flatten()
+        .map(|(name, range)| (name.clone(), range.clone()))
+        .collect::<Map<_, _>>();
+    (!requires.is_empty()).then_some(requires)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{parse, parse_package_json, remove_entry};
+
+    #[test]
+    fn v1_to_v3_works() {
+        let content = std::fs::read_to_string("tests/v1/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap().to_v3();
+        assert_eq!(lock_file.lockfile_version, 3);
+        assert!(lock_file.dependencies.is_none());
+
+        let packages = lock_file.packages_by_path.as_ref().unwrap();
+        let root = packages.get("").unwrap();
+        assert_eq!(root.name, Some("cxtl".to_string()));
+        let nested = packages
+            .get("node_modules/@babel/highlight/node_modules/js-tokens")
+            .unwrap();
+        assert_eq!(nested.version, "4.0.0");
+        assert!(nested.is_dev);
+
+        // the output is a valid lock file.
+        let json = lock_file.to_json().unwrap();
+        assert_eq!(parse(json).unwrap(), lock_file);
+    }
+
+    #[test]
+    fn v1_root_dependencies_work() {
+        let content = std::fs::read_to_string("tests/v1/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap();
+        let content = std::fs::read_to_string("tests/package.json").unwrap();
+        let manifest = parse_package_json(content).unwrap();
+        for converted in [lock_file.to_v2(), lock_file.to_v3()] {
+            let root = &converted.packages_by_path.as_ref().unwrap()[""];
+            let dependencies = root.dependencies.as_ref().unwrap();
+            assert_eq!(dependencies.get("rxjs"), Some(&"7.8.0".to_string()));
+            let dev_dependencies = root.dev_dependencies.as_ref().unwrap();
+            assert!(dev_dependencies.contains_key("jest"));
+            assert!(!dependencies.contains_key("jest"));
+            assert_eq!(manifest.check_lock_file(&converted), []);
+        }
+    }
+
+    #[test]
+    fn v3_to_v2_works() {
+        let content = std::fs::read_to_string("tests/v3/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap().to_v2();
+        assert_eq!(lock_file.lockfile_version, 2);
+        assert!(lock_file.packages_by_path.is_some());
+
+        // same as the dependencies npm wrote for the v2 file.
+        let content = std::fs::read_to_string("tests/v2/package-lock.json").unwrap();
+        let mut expected = parse(content).unwrap().dependencies.unwrap();
+        // the v3 file was written on a platform fsevents doesn't install on.
+        for name in ["fsevents", "bindings", "file-uri-to-path", "nan"] {
+            remove_entry(&mut expected, name);
+        }
+        assert_eq!(lock_file.dependencies, Some(expected));
+
+        let json = lock_file.to_json().unwrap();
+        assert_eq!(parse(json).unwrap(), lock_file);
+    }
+
+    #[test]
+    fn dev_optional_to_v1_works() {
+        let content = r#"{
+            "name": "app",
+            "lockfileVersion": 3,
+            "packages": {
+                "": { "name": "app" },
+                "node_modules/fsevents": { "version": "2.3.3", "devOptional": true }
+            }
+        }"#;
+        let lock_file = parse(content).unwrap().to_v1();
+        let fsevents = &lock_file.dependencies.unwrap()["fsevents"];
+        assert!(fsevents.is_dev);
+        assert!(fsevents.is_optional);
+    }
+
+    #[test]
+    fn workspaces_to_v1_works() {
+        let content = std::fs::read_to_string("tests/workspace/v3/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap().to_v1();
+        assert!(lock_file.packages_by_path.is_none());
+
+        let dependencies = lock_file.dependencies.unwrap();
+        let libb2 = dependencies.get("libb2").unwrap();
+        assert_eq!(libb2.version, "file:libb");
+        let liba = dependencies.get("liba").unwrap();
+        assert_eq!(liba.version, "file:liba");
+        assert!(liba.requires.as_ref().unwrap().contains_key("libb2"));
+    }
+}
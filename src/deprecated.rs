@@ -0,0 +1,169 @@
+use std::collections::{BTreeSet, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graph,
+    name::{folder_name, is_project_path},
+    Map, PackageLockJson, PackageName,
+};
+
+/// Installed package whose version is deprecated.
+#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct DeprecatedPackage {
+    pub name: PackageName,
+    pub version: String,
+    /// Install path of the package.
+    pub path: String,
+    /// Deprecation message.
+    pub message: String,
+    /// Direct dependencies of the project or its workspaces pulling the package in, sorted.
+    pub root_dependencies: Vec<PackageName>,
+}
+
+/// Deprecated packages pulled in by a direct dependency of the project.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DeprecationGroup {
+    pub root_dependency: PackageName,
+    pub packages: Vec<DeprecatedPackage>,
+}
+
+impl PackageLockJson {
+    /// Returns every deprecated installed package, sorted by path.
+    /// v1 files don't record deprecations, so they have none.
+    pub fn deprecated_packages(&self) -> Vec<DeprecatedPackage> {
+        let packages = self.v2_packages();
+        let mut deprecated = packages
+            .iter()
+            .filter(|(_, package)| !package.link)
+            .filter_map(|(path, package)| Some((path.as_str(), package.deprecated.as_ref()?)))
+            .map(|(path, message)| (path, (message, BTreeSet::new())))
+            .collect::<Map<_, _>>();
+        if deprecated.is_empty() {
+            return Vec::new();
+        }
+
+        // walk the tree of every direct dependency of the root project and the workspaces.
+        let projects = packages
+            .keys()
+            .map(String::as_str)
+            .filter(|path| is_project_path(path))
+            .collect::<HashSet<_>>();
+        for project in &projects {
+            for root in graph::edges(&packages, project) {
+                let Some(root_path) = root.path.filter(|path| !projects.contains(path)) else {
+                    continue;
+                };
+                let mut visited = HashSet::from([root_path]);
+                let mut pending = vec![root_path];
+                while let Some(path) = pending.pop() {
+                    if let Some((_, roots)) = deprecated.get_mut(path) {
+                        roots.insert(root.name);
+                    }
+                    for edge in graph::edges(&packages, path) {
+                        let Some(target) = edge.path else {
+                            continue;
+                        };
+                        if !projects.contains(target) && visited.insert(target) {
+                            pending.push(target);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut deprecated = deprecated
+            .into_iter()
+            .filter_map(|(path, (message, roots))| {
+                let name = match PackageName::new(folder_name(path).unwrap_or(path)) {
+                    Ok(name) => name,
+                    Err(e) => {
+                        tracing::warn!("Skipping {}: {}", path, e);
+                        return None;
+                    }
+                };
+                Some(DeprecatedPackage {
+                    name,
+                    version: packages[path].version.clone(),
+                    path: path.to_string(),
+                    message: message.clone(),
+                    root_dependencies: roots
+                        .into_iter()
+                        .filter_map(|root| PackageName::new(root).ok())
+                        .collect(),
+                })
+            })
+            .collect::<Vec<_>>();
+        deprecated.sort_by(|a, b| a.path.cmp(&b.path));
+        deprecated
+    }
+
+    /// Returns the deprecated packages grouped by the direct dependency pulling them in,
+    /// the groups with more packages first.
+    /// A package pulled in by several direct dependencies is in each of their groups.
+    pub fn deprecations_by_root_dependency(&self) -> Vec<DeprecationGroup> {
+        let mut groups = Map::<PackageName, Vec<DeprecatedPackage>>::new();
+        for package in self.deprecated_packages() {
+            for root in &package.root_dependencies {
+                groups
+                    .entry(root.clone())
+                    .or_default()
+                    .push(package.clone());
+            }
+        }
+        let mut groups = groups
+            .into_iter()
+            .map(|(root_dependency, packages)| DeprecationGroup {
+                root_dependency,
+                packages,
+            })
+            .collect::<Vec<_>>();
+        groups.sort_by(|a, b| {
+            b.packages
+                .len()
+                .cmp(&a.packages.len())
+                .then_with(|| a.root_dependency.cmp(&b.root_dependency))
+        });
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::parse;
+
+    #[test]
+    fn deprecated_packages_works() {
+        let content = std::fs::read_to_string("tests/cool-project/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap();
+        let deprecated = lock_file.deprecated_packages();
+        assert_eq!(deprecated.len(), 2);
+        let sourcemap_codec = &deprecated[1];
+        assert_eq!(sourcemap_codec.name, "sourcemap-codec");
+        assert_eq!(
+            sourcemap_codec.message,
+            "Please use @jridgewell/sourcemap-codec instead"
+        );
+        assert_eq!(
+            sourcemap_codec.root_dependencies,
+            ["@esbuild-plugins/node-modules-polyfill"]
+        );
+    }
+
+    #[test]
+    fn deprecations_by_root_dependency_works() {
+        let content = std::fs::read_to_string("tests/v3/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap();
+        let groups = lock_file.deprecations_by_root_dependency();
+        let summary = groups
+            .iter()
+            .map(|group| (group.root_dependency.as_str(), group.packages.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, [("jest", 9)]);
+        assert!(groups[0]
+            .packages
+            .iter()
+            .any(|package| package.name == "request"));
+    }
+}
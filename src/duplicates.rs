@@ -0,0 +1,225 @@
+use std::{cmp::Ordering, collections::BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graph, name::folder_name, semver::spec_range, Map, PackageLockJson, PackageName, Version,
+};
+
+/// Package installed at more than one version.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DuplicatePackage {
+    pub name: PackageName,
+    /// Installed versions, lowest first.
+    /// Versions that aren't semver, e.g. git urls, come last, sorted as text.
+    pub versions: Vec<String>,
+    /// Install path of every copy, sorted.
+    pub paths: Vec<String>,
+    /// Packages depending on it, sorted by path.
+    pub dependents: Vec<Dependent>,
+    /// Highest installed semver version accepted by every dependent,
+    /// `None` if no single version is.
+    pub dedupe_version: Option<String>,
+}
+
+/// Package depending on a duplicate package.
+#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Dependent {
+    /// Install path of the dependent, `""` for the root project.
+    pub path: String,
+    /// Range it requires.
+    pub range: String,
+    /// Version it currently gets.
+    pub version: String,
+}
+
+impl DuplicatePackage {
+    /// Number of installed copies, several of them can have the same version.
+    pub fn copies(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Returns whether `npm dedupe` could collapse every copy into a single version.
+    pub fn can_dedupe(&self) -> bool {
+        self.dedupe_version.is_some()
+    }
+}
+
+impl PackageLockJson {
+    /// Returns every package installed at more than one version, sorted by name.
+    ///
+    /// Nested copies are included. Only installed versions are taken as candidates
+    /// to dedupe to, and ranges that aren't semver, e.g. tags or git urls,
+    /// only accept the version they currently get.
+    pub fn duplicate_packages(&self) -> Vec<DuplicatePackage> {
+        let packages = self.v2_packages();
+        let mut copies = Map::<&str, Vec<&str>>::new();
+        for (path, package) in &packages {
+            if package.link {
+                continue;
+            }
+            let Some(folder_name) = folder_name(path) else {
+                // the root project and the workspaces.
+                continue;
+            };
+            let name = package.name.as_deref().unwrap_or(folder_name);
+            copies.entry(name).or_default().push(path);
+        }
+        copies.retain(|_, paths| {
+            let versions = paths
+                .iter()
+                .map(|path| &packages[*path].version)
+                .collect::<BTreeSet<_>>();
+            versions.len() > 1
+        });
+        if copies.is_empty() {
+            return Vec::new();
+        }
+
+        let names = copies
+            .iter()
+            .flat_map(|(name, paths)| paths.iter().map(move |path| (*path, *name)))
+            .collect::<Map<_, _>>();
+        let mut dependents = Map::<&str, Vec<Dependent>>::new();
+        for path in packages.keys() {
+            for edge in graph::edges(&packages, path) {
+                let Some(target) = edge.path else {
+                    continue;
+                };
+                let Some(&name) = names.get(target) else {
+                    continue;
+                };
+                dependents.entry(name).or_default().push(Dependent {
+                    path: path.clone(),
+                    range: edge.spec.to_string(),
+                    version: packages[target].version.clone(),
+                });
+            }
+        }
+
+        let mut duplicates = copies
+            .into_iter()
+            .filter_map(|(name, mut paths)| {
+                let Ok(package_name) = PackageName::new(name) else {
+                    tracing::warn!("{} is not a valid package name. Skipping it.", name);
+                    return None;
+                };
+                paths.sort_unstable();
+                // versions that aren't semver can't be dedupe candidates, but are listed.
+                let mut versions = paths
+                    .iter()
+                    .map(|path| {
+                        let version = &packages[*path].version;
+                        (version.parse::<Version>().ok(), version.clone())
+                    })
+                    .collect::<Vec<_>>();
+                versions.sort_by(|(a, a_raw), (b, b_raw)| match (a, b) {
+                    (Some(a), Some(b)) => a.cmp(b).then_with(|| a_raw.cmp(b_raw)),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => a_raw.cmp(b_raw),
+                });
+                versions.dedup_by(|(_, a), (_, b)| a == b);
+                let mut dependents = dependents.get(name).cloned().unwrap_or_default();
+                dependents.sort();
+                let dedupe_version = versions
+                    .iter()
+                    .rev()
+                    .filter_map(|(version, raw)| Some((version.as_ref()?, raw)))
+                    .find(|(version, raw)| {
+                        dependents
+                            .iter()
+                            .all(|dependent| match spec_range(&dependent.range) {
+                                Some(range) => range.satisfies(version),
+                                None => &dependent.version == *raw,
+                            })
+                    })
+                    .map(|(_, raw)| raw.clone());
+                Some(DuplicatePackage {
+                    name: package_name,
+                    versions: versions.into_iter().map(|(_, raw)| raw).collect(),
+                    paths: paths.into_iter().map(ToString::to_string).collect(),
+                    dependents,
+                    dedupe_version,
+                })
+            })
+            .collect::<Vec<_>>();
+        duplicates.sort_by(|a, b| a.name.cmp(&b.name));
+        duplicates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::parse;
+
+    #[test]
+    fn duplicate_packages_works() {
+        let content = std::fs::read_to_string("tests/v3/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap();
+        let duplicates = lock_file.duplicate_packages();
+        let find = |name: &str| {
+            duplicates
+                .iter()
+                .find(|duplicate| duplicate.name == name)
+                .unwrap()
+        };
+
+        let tslib = find("tslib");
+        assert_eq!(tslib.versions, ["1.14.1", "2.4.1"]);
+        assert_eq!(tslib.copies(), 3);
+        assert_eq!(tslib.dependents.len(), 3);
+        assert!(!tslib.can_dedupe());
+
+        // loose-envify takes both, babel-code-frame wants 3.
+        let js_tokens = find("js-tokens");
+        assert_eq!(js_tokens.dedupe_version, None);
+        assert!(js_tokens
+            .dependents
+            .iter()
+            .any(|dependent| dependent.range == "^3.0.0 || ^4.0.0"));
+
+        assert!(!duplicates.iter().any(|duplicate| duplicate.name == "rxjs"));
+    }
+
+    #[test]
+    fn duplicate_packages_suggest_dedupe() {
+        let content = r#"{
+            "name": "app",
+            "lockfileVersion": 3,
+            "packages": {
+                "": { "dependencies": { "a": "^1.0.0", "b": "^1.0.0" } },
+                "node_modules/a": { "version": "1.0.0" },
+                "node_modules/b": { "version": "1.0.0", "dependencies": { "a": "^1.1.0" } },
+                "node_modules/b/node_modules/a": { "version": "1.2.0" }
+            }
+        }"#;
+        let duplicates = parse(content).unwrap().duplicate_packages();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].versions, ["1.0.0", "1.2.0"]);
+        assert_eq!(duplicates[0].dedupe_version.as_deref(), Some("1.2.0"));
+    }
+
+    #[test]
+    fn duplicate_packages_keep_non_semver_versions() {
+        let content = r#"{
+            "name": "app",
+            "lockfileVersion": 3,
+            "packages": {
+                "": { "dependencies": { "a": "^1.0.0", "b": "^1.0.0" } },
+                "node_modules/a": { "version": "1.0.0" },
+                "node_modules/b": { "version": "1.0.0", "dependencies": { "a": "github:user/a" } },
+                "node_modules/b/node_modules/a": { "version": "git+ssh://git@github.com/user/a.git#abc" }
+            }
+        }"#;
+        let duplicates = parse(content).unwrap().duplicate_packages();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(
+            duplicates[0].versions,
+            ["1.0.0", "git+ssh://git@github.com/user/a.git#abc"]
+        );
+        // the git dependency only takes the version it gets.
+        assert_eq!(duplicates[0].dedupe_version, None);
+    }
+}
@@ -0,0 +1,440 @@
+use crate::{
+    convert::{fill_v1_entry, folder_links, v1_names},
+    graph::parent_folder,
+    package_key, remove_entry, Map, PackageLockJson, PackageLockJsonError, PackageName,
+    V1Dependency, V2Dependency,
+};
+
+impl PackageLockJson {
+    /// Returns the package installed at `path`, e.g. `node_modules/a/node_modules/b`.
+    pub fn package(&self, path: &str) -> Option<&V2Dependency> {
+        self.packages_by_path.as_ref()?.get(path)
+    }
+
+    /// Adds or replaces the package installed at `path`, keeping the packages nested in it.
+    /// Returns the replaced package.
+    ///
+    /// Both the `packages` and the `dependencies` sections are updated if present,
+    /// so v1 files only get the matching `dependencies` entry and return nothing.
+    /// Fails if the package `path` is nested in isn't installed,
+    /// or with v1 files, if `path` is a project folder as they only list installed packages.
+    pub fn insert_package(
+        &mut self,
+        path: impl Into<String>,
+        package: V2Dependency,
+    ) -> Result<Option<V2Dependency>, PackageLockJsonError> {
+        let path = path.into();
+        let previous = match &mut self.packages_by_path {
+            Some(packages) => {
+                let parent = parent_folder(&path);
+                if !parent.is_empty() && !packages.contains_key(parent) {
+                    return Err(PackageLockJsonError::PackageNotFoundError(
+                        parent.to_string(),
+                    ));
+                }
+                packages.insert(path.clone(), package)
+            }
+            None => {
+                let names = match v1_names(&Map::new(), &path) {
+                    Some(names) if !names.is_empty() => names,
+                    _ => {
+                        return Err(PackageLockJsonError::UnsupportedLockfileError(format!(
+                            "{path:?} is not installed in node_modules, v1 files can't list it"
+                        )))
+                    }
+                };
+                let dependencies = self.dependencies.get_or_insert_with(Map::new);
+                let entry = new_v1_entry(dependencies, &names).ok_or_else(|| {
+                    PackageLockJsonError::PackageNotFoundError(parent_folder(&path).to_string())
+                })?;
+                fill_v1_entry(entry, &path, &package, names[names.len() - 1]);
+                None
+            }
+        };
+        self.sync_v1_entry(&path);
+        let previous_key = previous
+            .as_ref()
+            .and_then(|previous| package_key(&path, previous));
+        self.refresh_package(&path, previous_key);
+        Ok(previous)
+    }
+
+    /// Removes the package installed at `path` and every package nested in it.
+    /// Returns the removed package, v1 files return nothing.
+    /// Fails if no package is installed at `path`, and for the root project, `""`,
+    /// which can't be removed.
+    pub fn remove_package(
+        &mut self,
+        path: &str,
+    ) -> Result<Option<V2Dependency>, PackageLockJsonError> {
+        if path.is_empty() {
+            return Err(PackageLockJsonError::PackageNotFoundError(
+                "the root project can't be removed".to_string(),
+            ));
+        }
+        let not_found = || PackageLockJsonError::PackageNotFoundError(path.to_string());
+        let nested = format!("{path}/node_modules/");
+        let removed = match &mut self.packages_by_path {
+            Some(packages) => {
+                let removed = remove_entry(packages, path).ok_or_else(not_found)?;
+                packages.retain(|key, _| !key.starts_with(&nested));
+                self.sync_v1_entry(path);
+                Some(removed)
+            }
+            None => {
+                let names = v1_names(&Map::new(), path).unwrap_or_default();
+                let dependencies = self.dependencies.as_mut().ok_or_else(not_found)?;
+                if find_v1_entry(dependencies, &names).is_none() {
+                    return Err(not_found());
+                }
+                remove_v1_entry(dependencies, &names);
+                None
+            }
+        };
+        let previous_key = removed
+            .as_ref()
+            .and_then(|removed| package_key(path, removed));
+        self.refresh_package(path, previous_key);
+        Ok(removed)
+    }
+
+    /// Changes the version of the package installed at `path`,
+    /// along with the url it is downloaded from and its checksum.
+    pub fn set_package_version(
+        &mut self,
+        path: &str,
+        version: impl Into<String>,
+        resolved: Option<String>,
+        integrity: Option<String>,
+    ) -> Result<(), PackageLockJsonError> {
+        let version = version.into();
+        let not_found = || PackageLockJsonError::PackageNotFoundError(path.to_string());
+        match &mut self.packages_by_path {
+            Some(packages) => {
+                let package = packages.get_mut(path).ok_or_else(not_found)?;
+                let previous_key = package_key(path, package);
+                package.version = version;
+                package.resolved = resolved;
+                package.integrity = integrity;
+                self.sync_v1_entry(path);
+                self.refresh_package(path, previous_key);
+            }
+            None => {
+                let names = v1_names(&Map::new(), path).unwrap_or_default();
+                let entry = self
+                    .dependencies
+                    .as_mut()
+                    .and_then(|dependencies| find_v1_entry(dependencies, &names))
+                    .ok_or_else(not_found)?;
+                // aliases keep the real name, npm:real-name@1.0.0.
+                let alias = entry
+                    .version
+                    .strip_prefix("npm:")
+                    .and_then(|alias| alias.rsplit_once('@'));
+                entry.version = match alias {
+                    Some((real_name, _)) => format!("npm:{real_name}@{version}"),
+                    None => version,
+                };
+                entry.resolved = resolved;
+                entry.integrity = integrity;
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes the `dependencies` entry of `path` match its `packages` entry,
+    /// for files having both sections.
+    fn sync_v1_entry(&mut self, path: &str) {
+        let (Some(packages), Some(dependencies)) = (&self.packages_by_path, &mut self.dependencies)
+        else {
+            return;
+        };
+        let links = folder_links(packages);
+        let Some(names) = v1_names(&links, path) else {
+            tracing::warn!("No link found for folder {}. Skipping it.", path);
+            return;
+        };
+        let Some(name) = names.last() else {
+            // the root project has no entry.
+            return;
+        };
+        match packages.get(path) {
+            Some(package) => match new_v1_entry(dependencies, &names) {
+                Some(entry) => fill_v1_entry(entry, path, package, name),
+                None => tracing::warn!("No dependencies entry found for the parent of {}.", path),
+            },
+            None => remove_v1_entry(dependencies, &names),
+        }
+    }
+
+    /// Updates the entry of the package installed at `path` in the name keyed view
+    /// of the `packages` section, `previous_key` being its key before the edit.
+    fn refresh_package(&mut self, path: &str, previous_key: Option<PackageName>) {
+        let (Some(packages), Some(packages_by_path)) = (&mut self.packages, &self.packages_by_path)
+        else {
+            return;
+        };
+        if let Some(key) = previous_key {
+            remove_entry(packages, key.as_str());
+        }
+        if let Some(package) = packages_by_path.get(path) {
+            if let Some(key) = package_key(path, package) {
+                packages.insert(key, package.clone());
+            }
+        }
+    }
+}
+
+fn find_v1_entry<'a>(
+    dependencies: &'a mut Map<String, V1Dependency>,
+    names: &[&str],
+) -> Option<&'a mut V1Dependency> {
+    let (first, rest) = names.split_first()?;
+    let entry = dependencies.get_mut(*first)?;
+    if rest.is_empty() {
+        Some(entry)
+    } else {
+        find_v1_entry(entry.dependencies.as_mut()?, rest)
+    }
+}
+
+/// Returns the entry for the given chain of names, creating it if needed.
+/// `None` if one of its parents is missing.
+fn new_v1_entry<'a>(
+    dependencies: &'a mut Map<String, V1Dependency>,
+    names: &[&str],
+) -> Option<&'a mut V1Dependency> {
+    let (name, parents) = names.split_last()?;
+    let siblings = match parents {
+        [] => dependencies,
+        _ => find_v1_entry(dependencies, parents)?
+            .dependencies
+            .get_or_insert_with(Map::new),
+    };
+    Some(siblings.entry(name.to_string()).or_default())
+}
+
+fn remove_v1_entry(dependencies: &mut Map<String, V1Dependency>, names: &[&str]) {
+    match names {
+        [] => {}
+        [name] => {
+            remove_entry(dependencies, name);
+        }
+        [parents @ .., name] => {
+            let Some(parent) = find_v1_entry(dependencies, parents) else {
+                return;
+            };
+            if let Some(siblings) = &mut parent.dependencies {
+                remove_entry(siblings, name);
+            }
+            // npm doesn't write empty sections.
+            if parent.dependencies.as_ref().is_some_and(Map::is_empty) {
+                parent.dependencies = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{normalize_packages, parse};
+
+    fn v2_lock_file() -> PackageLockJson {
+        let content = std::fs::read_to_string("tests/v2/package-lock.json").unwrap();
+        parse(content).unwrap()
+    }
+
+    #[test]
+    fn set_package_version_works() {
+        let mut lock_file = v2_lock_file();
+        let resolved = "https://registry.npmjs.org/rxjs/-/rxjs-7.8.1.tgz".to_string();
+        lock_file
+            .set_package_version(
+                "node_modules/rxjs",
+                "7.8.1",
+                Some(resolved.clone()),
+                Some("sha512-new".to_string()),
+            )
+            .unwrap();
+
+        let package = lock_file.package("node_modules/rxjs").unwrap();
+        assert_eq!(package.version, "7.8.1");
+        assert_eq!(
+            lock_file.packages.as_ref().unwrap()["rxjs"].version,
+            "7.8.1"
+        );
+        let dependency = &lock_file.dependencies.as_ref().unwrap()["rxjs"];
+        assert_eq!(dependency.version, "7.8.1");
+        assert_eq!(dependency.resolved, Some(resolved));
+        assert_eq!(dependency.integrity.as_deref(), Some("sha512-new"));
+
+        let err = lock_file.set_package_version("node_modules/missing", "1.0.0", None, None);
+        assert!(matches!(
+            err,
+            Err(PackageLockJsonError::PackageNotFoundError(_))
+        ));
+    }
+
+    #[test]
+    fn insert_and_remove_package_work() {
+        let mut lock_file = v2_lock_file();
+        let left_pad = V2Dependency {
+            version: "1.3.0".to_string(),
+            resolved: Some("https://registry.npmjs.org/left-pad/-/left-pad-1.3.0.tgz".to_string()),
+            ..V2Dependency::default()
+        };
+        let path = "node_modules/@babel/highlight/node_modules/left-pad";
+        assert!(lock_file.insert_package(path, left_pad).unwrap().is_none());
+        let highlight = &lock_file.dependencies.as_ref().unwrap()["@babel/highlight"];
+        let nested = highlight.dependencies.as_ref().unwrap();
+        assert_eq!(nested["left-pad"].version, "1.3.0");
+        assert!(nested.contains_key("js-tokens"));
+
+        let removed = lock_file
+            .remove_package("node_modules/@babel/highlight")
+            .unwrap()
+            .unwrap();
+        assert_eq!(removed.version, "7.18.6");
+        assert!(lock_file.package(path).is_none());
+        assert!(lock_file
+            .package("node_modules/@babel/highlight/node_modules/js-tokens")
+            .is_none());
+        assert!(!lock_file
+            .dependencies
+            .as_ref()
+            .unwrap()
+            .contains_key("@babel/highlight"));
+
+        // still a valid lock file with both sections in sync.
+        let json = lock_file.to_json().unwrap();
+        let reparsed = parse(json).unwrap();
+        assert_eq!(reparsed.packages_by_path, lock_file.packages_by_path);
+        assert_eq!(reparsed.dependencies, lock_file.dependencies);
+    }
+
+    #[test]
+    fn edit_v1_works() {
+        let content = std::fs::read_to_string("tests/v1/package-lock.json").unwrap();
+        let mut lock_file = parse(content).unwrap();
+        let path = "node_modules/@babel/highlight/node_modules/js-tokens";
+        lock_file
+            .set_package_version(path, "4.0.1", None, None)
+            .unwrap();
+        let highlight = &lock_file.dependencies.as_ref().unwrap()["@babel/highlight"];
+        let js_tokens = &highlight.dependencies.as_ref().unwrap()["js-tokens"];
+        assert_eq!(js_tokens.version, "4.0.1");
+        assert!(js_tokens.resolved.is_none());
+
+        lock_file.remove_package(path).unwrap();
+        let highlight = &lock_file.dependencies.as_ref().unwrap()["@babel/highlight"];
+        assert!(highlight.dependencies.is_none());
+        assert!(matches!(
+            lock_file.remove_package(path),
+            Err(PackageLockJsonError::PackageNotFoundError(missing)) if missing == path
+        ));
+
+        // aliases keep the name of the real package.
+        let mut lock_file = parse(
+            r#"{
+                "name": "app",
+                "lockfileVersion": 1,
+                "dependencies": {
+                    "string-width-cjs": { "version": "npm:string-width@4.2.3" }
+                }
+            }"#,
+        )
+        .unwrap();
+        lock_file
+            .set_package_version("node_modules/string-width-cjs", "4.2.4", None, None)
+            .unwrap();
+        let alias = &lock_file.dependencies.as_ref().unwrap()["string-width-cjs"];
+        assert_eq!(alias.version, "npm:string-width@4.2.4");
+    }
+
+    #[test]
+    fn remove_package_needs_package() {
+        let mut lock_file = v2_lock_file();
+        let before = lock_file.clone();
+        assert!(matches!(
+            lock_file.remove_package("node_modules/missing"),
+            Err(PackageLockJsonError::PackageNotFoundError(path)) if path == "node_modules/missing"
+        ));
+        assert_eq!(lock_file, before);
+    }
+
+    #[test]
+    fn insert_package_needs_parent() {
+        let package = V2Dependency {
+            version: "1.0.0".to_string(),
+            ..V2Dependency::default()
+        };
+        let mut lock_file = v2_lock_file();
+        let err = lock_file.insert_package("node_modules/missing/node_modules/a", package.clone());
+        assert!(matches!(
+            err,
+            Err(PackageLockJsonError::PackageNotFoundError(parent)) if parent == "node_modules/missing"
+        ));
+        assert!(lock_file.package("node_modules/missing").is_none());
+        assert!(!lock_file
+            .dependencies
+            .as_ref()
+            .unwrap()
+            .contains_key("missing"));
+        assert!(lock_file.remove_package("").is_err());
+        assert!(lock_file.package("").is_some());
+
+        let content = std::fs::read_to_string("tests/v1/package-lock.json").unwrap();
+        let mut lock_file = parse(content).unwrap();
+        let err = lock_file.insert_package("node_modules/missing/node_modules/a", package.clone());
+        assert!(matches!(
+            err,
+            Err(PackageLockJsonError::PackageNotFoundError(_))
+        ));
+        assert!(!lock_file
+            .dependencies
+            .as_ref()
+            .unwrap()
+            .contains_key("missing"));
+        let err = lock_file.insert_package("libs/a", package.clone());
+        assert!(matches!(
+            err,
+            Err(PackageLockJsonError::UnsupportedLockfileError(_))
+        ));
+        lock_file
+            .insert_package("node_modules/@babel/highlight/node_modules/a", package)
+            .unwrap();
+        let highlight = &lock_file.dependencies.as_ref().unwrap()["@babel/highlight"];
+        assert_eq!(
+            highlight.dependencies.as_ref().unwrap()["a"].version,
+            "1.0.0"
+        );
+    }
+
+    #[test]
+    fn edits_update_packages_by_name() {
+        let mut lock_file = v2_lock_file();
+        let left_pad = V2Dependency {
+            version: "1.3.0".to_string(),
+            ..V2Dependency::default()
+        };
+        lock_file
+            .insert_package("node_modules/left-pad", left_pad)
+            .unwrap();
+        lock_file
+            .remove_package("node_modules/@babel/highlight")
+            .unwrap();
+        lock_file
+            .set_package_version("node_modules/rxjs", "7.8.1", None, None)
+            .unwrap();
+
+        let packages = lock_file.packages.as_ref().unwrap();
+        assert_eq!(packages["left-pad"].version, "1.3.0");
+        assert!(!packages.contains_key("@babel/highlight"));
+        assert_eq!(
+            lock_file.packages,
+            lock_file.packages_by_path.as_ref().map(normalize_packages)
+        );
+    }
+}
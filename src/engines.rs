@@ -0,0 +1,444 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::{
+    graph, installed::workspace_names, name::folder_name, Map, PackageLockJson, PackageName,
+    V2Dependency, Version, VersionRange,
+};
+
+/// The `engines` of a package, e.g. `{ "node": ">=18" }`.
+///
+/// Legacy forms, an array of `"name range"` strings or a single one,
+/// are repaired into an object. Malformed values are recorded in `diagnostics`
+/// and kept to be written back as they were.
+/// Engines are equal if their ranges and malformed values are, however they were written.
+#[derive(Debug, Clone, Default)]
+pub struct Engines {
+    /// Range of every engine keyed by its name.
+    pub ranges: Map<String, EngineRange>,
+    /// Values of the engines that aren't a string, e.g. `{ "npm": 7 }`.
+    pub invalid: Map<String, Value>,
+    /// Entries of a legacy form that couldn't be read, e.g. `[1, "deno"]`,
+    /// or the whole value if it is neither an object nor a legacy form.
+    pub invalid_entries: Vec<Value>,
+    /// Form the engines were repaired from, if they were not an object.
+    pub repaired_from: Option<LegacyEngines>,
+    /// Values that couldn't be read.
+    pub diagnostics: Vec<String>,
+}
+
+/// Range of an engine as written, and parsed if it is valid.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EngineRange {
+    pub raw: String,
+    pub range: Option<VersionRange>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LegacyEngines {
+    /// `["node >=0.6.0"]`
+    Array,
+    /// `"node >=0.6.0"`
+    String,
+}
+
+/// Installed package whose `engines` don't accept the target runtime.
+#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct EngineIncompatibility {
+    pub name: PackageName,
+    pub version: String,
+    /// Install path of the package.
+    pub path: String,
+    /// Engine not accepted, e.g. `node`.
+    pub engine: String,
+    /// Range required by the package.
+    pub range: String,
+    /// Names of the packages leading to this one from the root project, itself included.
+    /// Paths from a workspace start with its name. `None` if no project depends on it.
+    pub dependency_path: Option<Vec<PackageName>>,
+}
+
+impl Engines {
+    /// Returns the parsed range of an engine.
+    pub fn get(&self, engine: &str) -> Option<&VersionRange> {
+        self.ranges.get(engine)?.range.as_ref()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty() && self.invalid.is_empty() && self.invalid_entries.is_empty()
+    }
+
+    /// Adds the range of an engine, recording a diagnostic if it is invalid.
+    pub fn insert(&mut self, engine: impl Into<String>, raw: impl Into<String>) {
+        let engine = engine.into();
+        let raw = raw.into();
+        let range = raw.parse().ok();
+        if range.is_none() {
+            self.diagnostics
+                .push(format!("Invalid range for engine {engine}: {raw}"));
+        }
+        self.ranges.insert(engine, EngineRange { raw, range });
+    }
+
+    fn from_value(value: Value) -> Self {
+        let mut engines = Self::default();
+        match value {
+            Value::Object(ranges) => {
+                for (engine, range) in ranges {
+                    match range {
+                        Value::String(range) => engines.insert(engine, range),
+                        range => {
+                            engines
+                                .diagnostics
+                                .push(format!("Invalid range for engine {engine}: {range}"));
+                            engines.invalid.insert(engine, range);
+                        }
+                    }
+                }
+            }
+            Value::Array(entries) => {
+                engines.repaired_from = Some(LegacyEngines::Array);
+                for entry in entries {
+                    match entry {
+                        Value::String(entry) => engines.insert_legacy(entry),
+                        entry => {
+                            engines
+                                .diagnostics
+                                .push(format!("Invalid engine entry: {entry}"));
+                            engines.invalid_entries.push(entry);
+                        }
+                    }
+                }
+            }
+            Value::String(entry) => {
+                engines.repaired_from = Some(LegacyEngines::String);
+                engines.insert_legacy(entry);
+            }
+            value => {
+                engines
+                    .diagnostics
+                    .push(format!("Invalid engines: {value}"));
+                engines.invalid_entries.push(value);
+            }
+        }
+        engines
+    }
+
+    /// Adds a legacy `"name range"` entry.
+    fn insert_legacy(&mut self, entry: String) {
+        match split_legacy_entry(&entry) {
+            Some((engine, range)) => self.insert(engine, range),
+            None => {
+                self.diagnostics
+                    .push(format!("Invalid engine entry: {}", entry.trim()));
+                self.invalid_entries.push(Value::String(entry));
+            }
+        }
+    }
+
+    /// Returns whether the engines have to be written as they were read
+    /// to keep what couldn't be repaired, or an empty legacy form.
+    fn keeps_written_form(&self) -> bool {
+        let has_values = !self.ranges.is_empty() || !self.invalid.is_empty();
+        match self.repaired_from {
+            Some(_) => !self.invalid_entries.is_empty() || !has_values,
+            None => !self.invalid_entries.is_empty() && !has_values,
+        }
+    }
+}
+
+/// Splits a legacy `"name range"` entry, e.g. `node >=0.6.0` or `npm>=1.3`.
+pub(crate) fn split_legacy_entry(entry: &str) -> Option<(&str, &str)> {
+    let entry = entry.trim();
+    match entry.find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '=' | '~' | '^')) {
+        Some(end) if end > 0 => {
+            let (engine, range) = entry.split_at(end);
+            Some((engine, range.trim()))
+        }
+        _ => None,
+    }
+}
+
+impl PartialEq for Engines {
+    fn eq(&self, other: &Self) -> bool {
+        self.ranges == other.ranges
+            && self.invalid == other.invalid
+            && self.invalid_entries == other.invalid_entries
+    }
+}
+
+impl Eq for Engines {}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for Engines {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut engines = Self::default();
+        for (engine, range) in iter {
+            engines.insert(engine, range);
+        }
+        engines
+    }
+}
+
+impl<'de> Deserialize<'de> for Engines {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Value::deserialize(deserializer).map(Self::from_value)
+    }
+}
+
+/// Written as an object, sorted by engine unless the order is preserved.
+/// Legacy forms with entries that couldn't be repaired, or with none at all,
+/// are written back as they were, with the ranges as `"name range"` entries.
+/// So are values that are neither.
+impl Serialize for Engines {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !self.keeps_written_form() {
+            let mut values = self
+                .ranges
+                .iter()
+                .map(|(engine, range)| (engine, Value::String(range.raw.clone())))
+                .chain(
+                    self.invalid
+                        .iter()
+                        .map(|(engine, value)| (engine, value.clone())),
+                )
+                .collect::<Vec<_>>();
+            if cfg!(not(feature = "preserve_order")) {
+                values.sort_by_key(|(engine, _)| *engine);
+            }
+            return serializer.collect_map(values);
+        }
+        let entries = self
+            .ranges
+            .iter()
+            .map(|(engine, range)| Value::String(format!("{engine} {}", range.raw)))
+            .chain(self.invalid_entries.iter().cloned())
+            .collect::<Vec<_>>();
+        match (self.repaired_from, &entries[..]) {
+            (Some(LegacyEngines::String) | None, [entry]) => entry.serialize(serializer),
+            _ => serializer.collect_seq(entries),
+        }
+    }
+}
+
+impl PackageLockJson {
+    /// Returns every installed package whose `engines` exclude the target runtime,
+    /// e.g. `node` 18.19.0 and `npm` 10.2.0.
+    ///
+    /// Engines missing from the target and invalid ranges are not checked.
+    /// The dependency path is the shortest one from the root project or a workspace.
+    /// Results are sorted by install path.
+    pub fn incompatible_engines(
+        &self,
+        target: &Map<String, Version>,
+    ) -> Vec<EngineIncompatibility> {
+        let packages = self.v2_packages();
+        let dependency_paths = dependency_paths(&packages);
+        let mut incompatibilities = Vec::new();
+        for (path, package) in &packages {
+            if path.is_empty() || package.link {
+                continue;
+            }
+            for (engine, range) in package.engines.iter().flat_map(|engines| &engines.ranges) {
+                let (Some(version), Some(spec)) = (target.get(engine), &range.range) else {
+                    continue;
+                };
+                if spec.satisfies(version) {
+                    continue;
+                }
+                let name = match PackageName::new(folder_name(path).unwrap_or(path)) {
+                    Ok(name) => name,
+                    Err(e) => {
+                        tracing::warn!("Skipping {}: {}", path, e);
+                        continue;
+                    }
+                };
+                let dependency_path = dependency_paths.get(path.as_str()).cloned();
+                incompatibilities.push(EngineIncompatibility {
+                    name,
+                    version: package.version.clone(),
+                    path: path.clone(),
+                    engine: engine.clone(),
+                    range: range.raw.clone(),
+                    dependency_path,
+                });
+            }
+        }
+        incompatibilities.sort_by(|a, b| (&a.path, &a.engine).cmp(&(&b.path, &b.engine)));
+        incompatibilities
+    }
+}
+
+/// Returns the shortest chain of package names from the root project to every installed package.
+/// Workspaces start a chain with their name, so their dependencies have one too.
+fn dependency_paths(packages: &Map<String, V2Dependency>) -> Map<&str, Vec<PackageName>> {
+    let mut paths = Map::from([("", Vec::new())]);
+    let mut pending = VecDeque::from([""]);
+    let mut workspaces = workspace_names(packages).into_iter().collect::<Vec<_>>();
+    workspaces.sort();
+    for (folder, name) in workspaces {
+        paths.insert(folder, vec![name]);
+        pending.push_back(folder);
+    }
+    while let Some(path) = pending.pop_front() {
+        for edge in graph::edges(packages, path) {
+            let (Some(target), Ok(name)) = (edge.path, PackageName::new(edge.name)) else {
+                continue;
+            };
+            if paths.contains_key(target) {
+                continue;
+            }
+            let mut names = paths[path].clone();
+            names.push(name);
+            paths.insert(target, names);
+            pending.push_back(target);
+        }
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn engines_repair_legacy_forms() {
+        let engines: Engines =
+            serde_json::from_str(r#"["node >=0.6.0", "npm>=1.3", 1, "deno"]"#).unwrap();
+        assert_eq!(engines.repaired_from, Some(LegacyEngines::Array));
+        assert!(engines
+            .get("node")
+            .unwrap()
+            .satisfies(&"0.6.0".parse().unwrap()));
+        assert_eq!(engines.ranges["npm"].raw, ">=1.3");
+        assert_eq!(
+            engines.diagnostics,
+            ["Invalid engine entry: 1", "Invalid engine entry: deno"]
+        );
+
+        let engines: Engines = serde_json::from_str(r#""node >= 0.4""#).unwrap();
+        assert_eq!(engines.repaired_from, Some(LegacyEngines::String));
+        assert_eq!(engines.ranges["node"].raw, ">= 0.4");
+
+        let engines: Engines = serde_json::from_str(r#"{ "node": "latest", "npm": 7 }"#).unwrap();
+        assert!(engines.repaired_from.is_none());
+        assert!(engines.ranges["node"].range.is_none());
+        assert_eq!(engines.diagnostics.len(), 2);
+        assert_eq!(
+            serde_json::to_string(&engines).unwrap(),
+            r#"{"node":"latest","npm":7}"#
+        );
+    }
+
+    #[test]
+    fn engines_write_back_what_they_cant_repair() {
+        let write = |json: &str| {
+            let engines: Engines = serde_json::from_str(json).unwrap();
+            serde_json::to_string(&engines).unwrap()
+        };
+        assert_eq!(write(r#"["node >=0.6.0"]"#), r#"{"node":">=0.6.0"}"#);
+        assert_eq!(write("[]"), "[]");
+        assert_eq!(
+            write(r#"["node >=0.6.0", 1, "deno"]"#),
+            r#"["node >=0.6.0",1,"deno"]"#
+        );
+        assert_eq!(write(r#""deno""#), r#""deno""#);
+        assert_eq!(write("true"), "true");
+    }
+
+    #[test]
+    fn incompatible_engines_works() {
+        let content = std::fs::read_to_string("tests/cool-project/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap();
+        let target = Map::from([
+            ("node".to_string(), "14.0.0".parse().unwrap()),
+            ("npm".to_string(), "7.0.0".parse().unwrap()),
+        ]);
+        let incompatibilities = lock_file.incompatible_engines(&target);
+        assert!(incompatibilities
+            .iter()
+            .all(|incompatibility| incompatibility.engine == "node"));
+
+        let dd_trace = incompatibilities
+            .iter()
+            .find(|incompatibility| incompatibility.name == "dd-trace")
+            .unwrap();
+        assert_eq!(dd_trace.range, ">=16");
+        assert_eq!(
+            dd_trace.dependency_path,
+            Some(vec!["dd-trace".parse().unwrap()])
+        );
+
+        let default_browser = incompatibilities
+            .iter()
+            .find(|incompatibility| incompatibility.name == "default-browser")
+            .unwrap();
+        assert_eq!(default_browser.dependency_path.as_ref().unwrap()[0], "open");
+
+        // paths from a workspace start with its name.
+        let type_fest = incompatibilities
+            .iter()
+            .find(|incompatibility| incompatibility.path == "libs/lib1/node_modules/type-fest")
+            .unwrap();
+        assert_eq!(type_fest.name, "type-fest");
+        assert_eq!(
+            type_fest.dependency_path,
+            Some(vec![
+                "@cool-project/lib1".parse().unwrap(),
+                "type-fest".parse().unwrap()
+            ])
+        );
+
+        // node 18 is fine, npm 1.0.0 is not.
+        let target = Map::from([
+            ("node".to_string(), "18.19.0".parse().unwrap()),
+            ("npm".to_string(), "1.0.0".parse().unwrap()),
+        ]);
+        let paths = lock_file
+            .incompatible_engines(&target)
+            .into_iter()
+            .map(|incompatibility| incompatibility.path)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "node_modules/body-parser",
+                "node_modules/destroy",
+                "node_modules/express/node_modules/body-parser",
+                "node_modules/smart-buffer",
+                "node_modules/socks",
+            ]
+        );
+    }
+
+    #[test]
+    fn incompatible_engines_of_unreachable_packages() {
+        let content = r#"{
+            "name": "app",
+            "lockfileVersion": 3,
+            "packages": {
+                "": { "dependencies": { "a": "^1.0.0" } },
+                "node_modules/a": { "version": "1.0.0", "engines": { "node": ">=99" } },
+                "node_modules/b": { "version": "1.0.0", "engines": { "node": ">=99" } }
+            }
+        }"#;
+        let target = Map::from([("node".to_string(), "18.0.0".parse().unwrap())]);
+        let incompatibilities = parse(content).unwrap().incompatible_engines(&target);
+        let paths = incompatibilities
+            .iter()
+            .map(|incompatibility| {
+                (
+                    incompatibility.name.as_str(),
+                    incompatibility.dependency_path.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [("a", Some(vec!["a".parse().unwrap()])), ("b", None)]
+        );
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+
+use crate::{
+    graph,
+    hoist::{self, Dependency, Node},
+    installed::workspace_names,
+    name::is_project_path,
+    normalize_packages, Map, PackageLockJson, PackageLockJsonError, V2Dependency,
+};
+
+impl PackageLockJson {
+    /// Returns a standalone lock file for the workspace `name`, e.g. to install
+    /// a single service of a monorepo in its own Docker image.
+    /// The workspace can also be given by its folder.
+    ///
+    /// The workspace becomes the root project and only the packages it needs,
+    /// dev dependencies included, are kept. They are placed again from the new root,
+    /// so packages nested because of conflicts elsewhere in the monorepo move up,
+    /// and their `dev` and `optional` flags are computed again.
+    /// Other workspaces it depends on are kept as links to their folder, relative
+    /// to the one of the workspace, with their own dependencies installed in them,
+    /// the way npm installs `file:` dependencies. They have to be next to it to install.
+    pub fn extract_workspace(&self, name: &str) -> Result<Self, PackageLockJsonError> {
+        let not_found = || PackageLockJsonError::WorkspaceNotFoundError(name.to_string());
+        let packages = self.packages_by_path.as_ref().ok_or_else(not_found)?;
+        let workspaces = workspace_names(packages);
+        let (folder, workspace_name) = workspaces
+            .iter()
+            .find(|(folder, workspace)| *workspace == name || **folder == name)
+            .ok_or_else(not_found)?;
+
+        let mut root = packages[*folder].clone();
+        root.name = Some(workspace_name.to_string());
+        let mut extracted = Map::from([(String::new(), root)]);
+        // folder in the new lock file and in the monorepo of every project to place.
+        let mut pending = vec![(String::new(), *folder)];
+        let mut linked = HashSet::from([*folder]);
+        while let Some((prefix, source)) = pending.pop() {
+            let (nodes, paths, links) = dependency_nodes(packages, source);
+            for (path, node) in hoist::place(&nodes, 0) {
+                extracted.insert(join(&prefix, &path), packages[paths[node]].clone());
+            }
+            for (link_name, target) in links {
+                let resolved = relative_path(folder, target);
+                let link = V2Dependency {
+                    resolved: Some(resolved.clone()),
+                    link: true,
+                    ..V2Dependency::default()
+                };
+                extracted.insert(join(&prefix, &format!("node_modules/{link_name}")), link);
+                if linked.insert(target) {
+                    extracted.insert(resolved.clone(), packages[target].clone());
+                    pending.push((resolved, target));
+                }
+            }
+        }
+        hoist::set_flags(&mut extracted);
+
+        let version = &packages[*folder].version;
+        let lock_file = Self {
+            name: workspace_name.to_string(),
+            version: (!version.is_empty()).then(|| version.clone()),
+            lockfile_version: 3,
+            packages: Some(normalize_packages(&extracted)),
+            packages_by_path: Some(extracted),
+            dependencies: None,
+            extra: self.extra.clone(),
+        };
+        Ok(match self.lockfile_version {
+            2 => lock_file.to_v2(),
+            _ => lock_file,
+        })
+    }
+}
+
+/// Returns one node per package `folder` needs, copies installed in several places
+/// being the same node, the install path of every node and the workspaces it needs.
+fn dependency_nodes<'a>(
+    packages: &'a Map<String, V2Dependency>,
+    folder: &'a str,
+) -> (Vec<Node>, Vec<&'a str>, Vec<(&'a str, &'a str)>) {
+    let mut paths = vec![folder];
+    let mut ids = Map::new();
+    let mut nodes = vec![Node::default()];
+    let mut links = Vec::new();
+    let mut next = 0;
+    while let Some(&path) = paths.get(next) {
+        for edge in graph::edges(packages, path) {
+            let Some(target) = edge.path else {
+                continue;
+            };
+            if is_project_path(target) {
+                if !links.contains(&(edge.name, target)) {
+                    links.push((edge.name, target));
+                }
+                continue;
+            }
+            let package = &packages[target];
+            let id = (
+                package.name.as_deref().unwrap_or(edge.name),
+                package.version.as_str(),
+                package.resolved.as_deref(),
+            );
+            let node = *ids.entry(id).or_insert_with(|| {
+                paths.push(target);
+                nodes.push(Node::default());
+                nodes.len() - 1
+            });
+            nodes[next].dependencies.push(Dependency {
+                name: edge.name.to_string(),
+                node,
+                alternatives: Vec::new(),
+            });
+        }
+        next += 1;
+    }
+    (nodes, paths, links)
+}
+
+fn join(folder: &str, path: &str) -> String {
+    if folder.is_empty() {
+        path.to_string()
+    } else {
+        format!("{folder}/{path}")
+    }
+}
+
+/// Returns the path of the folder `to` from the folder `from`, e.g. `../b` from `libs/a` to `libs/b`.
+fn relative_path(from: &str, to: &str) -> String {
+    let from = from
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    let to = to
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut segments = vec![".."; from.len() - common];
+    segments.extend(&to[common..]);
+    segments.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn extract_workspace_works() {
+        let content = std::fs::read_to_string("tests/cool-project/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap();
+        let extracted = lock_file.extract_workspace("@cool-project/lib2").unwrap();
+        assert_eq!(extracted.name, "@cool-project/lib2");
+        assert_eq!(extracted.lockfile_version, 2);
+
+        let packages = extracted.packages_by_path.as_ref().unwrap();
+        assert!(packages.keys().all(|path| path.starts_with("node_modules/")
+            || path.starts_with("../lib1")
+            || path.is_empty()));
+        // nested in the workspace folder of the monorepo, top level now.
+        assert_eq!(packages["node_modules/dotenv"].version, "16.0.3");
+        assert_eq!(packages["node_modules/type-fest"].version, "3.11.0");
+        assert!(!packages["node_modules/date-fns"].is_dev);
+        assert!(packages["node_modules/chai"].is_dev);
+
+        // the workspace it depends on is linked, with its own dependencies.
+        let lib1 = &packages["node_modules/@cool-project/lib1"];
+        assert!(lib1.link);
+        assert_eq!(lib1.resolved.as_deref(), Some("../lib1"));
+        assert_eq!(
+            packages["../lib1"].name.as_deref(),
+            Some("@cool-project/lib1")
+        );
+        assert!(packages.contains_key("../lib1/node_modules/mocha"));
+        assert!(packages["../lib1/node_modules/mocha"].is_dev);
+
+        // every package has its dependencies installed.
+        for path in packages.keys() {
+            for edge in graph::edges(packages, path) {
+                if !edge.is_optional && !edge.is_peer {
+                    assert!(edge.path.is_some(), "{} of {}", edge.name, path);
+                }
+            }
+        }
+
+        let json = extracted.to_json().unwrap();
+        // parsing replaces the `file:` version of the linked workspace in v1 `dependencies`.
+        assert_eq!(
+            parse(json).unwrap().packages_by_path,
+            extracted.packages_by_path
+        );
+
+        let err = lock_file.extract_workspace("missing");
+        assert!(matches!(
+            err,
+            Err(PackageLockJsonError::WorkspaceNotFoundError(_))
+        ));
+    }
+
+    #[test]
+    fn extract_workspace_nests_conflicts() {
+        let content = r#"{
+            "name": "mono",
+            "lockfileVersion": 3,
+            "packages": {
+                "": { "name": "mono", "workspaces": ["a"] },
+                "a": { "name": "a", "version": "1.0.0", "dependencies": { "x": "^1.0.0", "y": "^1.0.0" } },
+                "a/node_modules/x": { "version": "1.0.0" },
+                "node_modules/a": { "resolved": "a", "link": true },
+                "node_modules/x": { "version": "2.0.0" },
+                "node_modules/y": { "version": "1.0.0", "dependencies": { "x": "^2.0.0" } }
+            }
+        }"#;
+        let extracted = parse(content).unwrap().extract_workspace("a").unwrap();
+        let packages = extracted.packages_by_path.unwrap();
+        let mut paths = packages
+            .iter()
+            .map(|(path, package)| (path.as_str(), package.version.as_str()))
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                ("", "1.0.0"),
+                ("node_modules/x", "1.0.0"),
+                ("node_modules/y", "1.0.0"),
+                ("node_modules/y/node_modules/x", "2.0.0"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{name::is_project_path, Map, V2Dependency};
+
+/// A dependency of a package and the package it resolves to.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) struct Edge<'a> {
+    pub name: &'a str,
+    pub spec: &'a str,
+    /// Install path of the package, `None` if it isn't installed.
+    pub path: Option<&'a str>,
+    pub is_dev: bool,
+    pub is_optional: bool,
+    pub is_peer: bool,
+}
+
+/// Returns the folder `path` is installed in: `node_modules/a/node_modules/b` is in `node_modules/a`.
+/// Top level packages and workspaces are in the root project, `""`.
+pub(crate) fn parent_folder(path: &str) -> &str {
+    path.rsplit_once("/node_modules/")
+        .map_or("", |(parent, _)| parent)
+}
+
+/// Returns the install path of the package `name` required from `from`,
+/// following links to their target folder.
+pub(crate) fn resolve<'a>(
+    packages: &'a Map<String, V2Dependency>,
+    from: &str,
+    name: &str,
+) -> Option<&'a str> {
+    let mut folder = from;
+    loop {
+        let candidate = if folder.is_empty() {
+            format!("node_modules/{name}")
+        } else {
+            format!("{folder}/node_modules/{name}")
+        };
+        if let Some((path, package)) = packages.get_key_value(&candidate) {
+            if !package.link {
+                return Some(path);
+            }
+            let target = package.resolved.as_deref()?;
+            return packages
+                .get_key_value(target)
+                .map(|(path, _)| path.as_str());
+        }
+        if folder.is_empty() {
+            return None;
+        }
+        folder = parent_folder(folder);
+    }
+}
+
+/// Returns the dependencies of the package installed at `path`.
+/// Dev dependencies only count for the root project and workspaces.
+pub(crate) fn edges<'a>(packages: &'a Map<String, V2Dependency>, path: &str) -> Vec<Edge<'a>> {
+    let Some(package) = packages.get(path) else {
+        return Vec::new();
+    };
+    let is_project = is_project_path(path);
+    let kinds = [
+        (&package.dependencies, false, false, false),
+        (&package.optional_dependencies, false, true, false),
+        (&package.peer_dependencies, false, false, true),
+        (&package.dev_dependencies, true, false, false),
+    ];
+    let mut edges = Vec::new();
+    for (dependencies, is_dev, is_optional, is_peer) in kinds {
+        if is_dev && !is_project {
+            continue;
+        }
+        for (name, spec) in dependencies.iter().flatten() {
+            edges.push(Edge {
+                name,
+                spec,
+                path: resolve(packages, path, name),
+                is_dev,
+                is_optional,
+                is_peer,
+            });
+        }
+    }
+    if is_project && edges.is_empty() {
+        // v1 files don't record the dependencies of the root project, nor do folders
+        // without a manifest, so every package installed in them is taken as one.
+        let prefix = if path.is_empty() {
+            "node_modules/".to_string()
+        } else {
+            format!("{path}/node_modules/")
+        };
+        edges = packages
+            .iter()
+            .filter(|(_, package)| !package.link)
+            .filter_map(|(path, package)| {
+                let name = path.strip_prefix(&prefix)?;
+                (!name.contains("/node_modules/")).then_some(Edge {
+                    name,
+                    spec: "",
+                    path: Some(path),
+                    is_dev: package.is_dev,
+                    is_optional: package.is_optional,
+                    is_peer: false,
+                })
+            })
+            .collect();
+    }
+    edges.sort_by_key(|edge| edge.name);
+    edges
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn resolve_works() {
+        let content = std::fs::read_to_string("tests/v3/package-lock.json").unwrap();
+        let packages = parse(content).unwrap().packages_by_path.unwrap();
+        assert_eq!(
+            resolve(&packages, "node_modules/tslint", "tslib"),
+            Some("node_modules/tslint/node_modules/tslib")
+        );
+        assert_eq!(
+            resolve(&packages, "node_modules/rxjs", "tslib"),
+            Some("node_modules/tslib")
+        );
+        assert_eq!(resolve(&packages, "node_modules/rxjs", "missing"), None);
+
+        let content = std::fs::read_to_string("tests/workspace/v3/package-lock.json").unwrap();
+        let packages = parse(content).unwrap().packages_by_path.unwrap();
+        // links resolve to the workspace folder.
+        assert_eq!(resolve(&packages, "liba", "libb2"), Some("libb"));
+    }
+}
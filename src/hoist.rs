@@ -0,0 +1,543 @@
+use std::collections::{BTreeSet, HashSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graph::{self, parent_folder, Edge},
+    name::{folder_name, is_project_path},
+    semver::spec_range,
+    Map, PackageJson, PackageLockJson, PackageLockJsonError, V2Dependency, Version,
+};
+
+/// Install path where the layout of a lock file differs from the one npm would build.
+#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct LayoutDifference {
+    pub path: String,
+    /// Version npm would install there, `None` if nothing.
+    pub expected: Option<String>,
+    /// Version installed there, `None` if nothing.
+    pub actual: Option<String>,
+}
+
+/// A package to place in `node_modules` and the packages it depends on.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Node {
+    pub dependencies: Vec<Dependency>,
+}
+
+/// A dependency of a node, the node it requires and how.
+#[derive(Debug, Clone)]
+pub(crate) struct Dependency {
+    /// Folder name the dependency is required under, the alias for aliased packages.
+    pub name: String,
+    pub node: usize,
+    /// Other nodes the dependency accepts, reused if they are already reachable.
+    pub alternatives: Vec<usize>,
+}
+
+/// Places the dependencies of `root` the way npm does, every package as close
+/// to the root project as it can be without another version shadowing it.
+///
+/// Dependencies are placed breadth first, sorted by name.
+/// Returns the node installed at every install path.
+pub(crate) fn place(nodes: &[Node], root: usize) -> Map<String, usize> {
+    let mut placed = Map::new();
+    // folder of every dependent and the install path its dependency resolves to, by name.
+    let mut resolved = Map::<String, Vec<(String, String)>>::new();
+    let mut pending = VecDeque::from([(String::new(), root)]);
+    while let Some((folder, node)) = pending.pop_front() {
+        let mut dependencies = nodes[node].dependencies.iter().collect::<Vec<_>>();
+        dependencies.sort_by(|a, b| a.name.cmp(&b.name));
+        for dependency in dependencies {
+            let (path, is_new) = place_dependency(&placed, &resolved, &folder, dependency);
+            if is_new {
+                placed.insert(path.clone(), dependency.node);
+                pending.push_back((path.clone(), dependency.node));
+            }
+            resolved
+                .entry(dependency.name.clone())
+                .or_default()
+                .push((folder.clone(), path));
+        }
+    }
+    placed
+}
+
+/// Returns the install path of a dependency required from `from`
+/// and whether it has to be installed there.
+fn place_dependency(
+    placed: &Map<String, usize>,
+    resolved: &Map<String, Vec<(String, String)>>,
+    from: &str,
+    dependency: &Dependency,
+) -> (String, bool) {
+    let mut target = None;
+    let mut folder = from;
+    loop {
+        let path = install_path(folder, &dependency.name);
+        match placed.get(&path) {
+            Some(node) if accepts(dependency, *node) => return (path, false),
+            Some(_) => break,
+            None if shadows(placed, resolved, folder, dependency) => break,
+            None => target = Some(path),
+        }
+        if folder.is_empty() {
+            break;
+        }
+        folder = parent_folder(folder);
+    }
+    match target {
+        Some(path) => (path, true),
+        None => {
+            // nothing better than the folder of the dependent.
+            let path = install_path(from, &dependency.name);
+            let is_new = !placed.contains_key(&path);
+            (path, is_new)
+        }
+    }
+}
+
+/// Returns whether installing the dependency in `folder` would change what
+/// a package already placed inside the folder resolves the same name to.
+fn shadows(
+    placed: &Map<String, usize>,
+    resolved: &Map<String, Vec<(String, String)>>,
+    folder: &str,
+    dependency: &Dependency,
+) -> bool {
+    resolved
+        .get(&dependency.name)
+        .into_iter()
+        .flatten()
+        .any(|(dependent, path)| {
+            is_within(dependent, folder)
+                && !is_within(parent_folder(path), folder)
+                && placed.get(path) != Some(&dependency.node)
+        })
+}
+
+fn accepts(dependency: &Dependency, node: usize) -> bool {
+    dependency.node == node || dependency.alternatives.contains(&node)
+}
+
+fn is_within(path: &str, folder: &str) -> bool {
+    folder.is_empty()
+        || path == folder
+        || path
+            .strip_prefix(folder)
+            .is_some_and(|rest| rest.starts_with("/node_modules/"))
+}
+
+fn install_path(folder: &str, name: &str) -> String {
+    if folder.is_empty() {
+        format!("node_modules/{name}")
+    } else {
+        format!("{folder}/node_modules/{name}")
+    }
+}
+
+/// Sets the `dev`, `optional` and `devOptional` flags of every package the way npm does:
+/// `dev` if only dev dependencies of the root project and workspaces pull it in,
+/// `optional` if only optional dependencies do, and
+/// `devOptional` if it takes one or the other, but neither alone.
+pub(crate) fn set_flags(packages: &mut Map<String, V2Dependency>) {
+    // the root project and the workspaces.
+    let projects = packages
+        .keys()
+        .map(String::as_str)
+        .filter(|path| is_project_path(path))
+        .collect::<Vec<_>>();
+    let reachable = |skip: &dyn Fn(&Edge, bool) -> bool| {
+        let mut reachable = projects.iter().copied().collect::<HashSet<_>>();
+        let mut pending = projects.clone();
+        while let Some(path) = pending.pop() {
+            for edge in graph::edges(packages, path) {
+                let Some(target) = edge.path.filter(|_| !skip(&edge, is_project_path(path))) else {
+                    continue;
+                };
+                if reachable.insert(target) {
+                    pending.push(target);
+                }
+            }
+        }
+        reachable
+    };
+    // only projects have dev dependencies.
+    let without_dev = reachable(&|edge, from_project| from_project && edge.is_dev);
+    let without_optional = reachable(&|edge, _| edge.is_optional);
+    let production =
+        reachable(&|edge, from_project| edge.is_optional || (from_project && edge.is_dev));
+    let flags = packages
+        .iter()
+        .filter(|(path, _)| !path.is_empty())
+        .map(|(path, package)| {
+            // links take the flags of their target.
+            let target = match &package.resolved {
+                Some(target) if package.link => target.as_str(),
+                _ => path.as_str(),
+            };
+            let is_dev = !without_dev.contains(target);
+            let is_optional = !without_optional.contains(target);
+            let is_dev_optional = !production.contains(target) && !is_dev && !is_optional;
+            (path.clone(), (is_dev, is_optional, is_dev_optional))
+        })
+        .collect::<Vec<_>>();
+    for (path, (is_dev, is_optional, is_dev_optional)) in flags {
+        if let Some(package) = packages.get_mut(&path) {
+            package.is_dev = is_dev;
+            package.is_optional = is_optional;
+            package.is_dev_optional = is_dev_optional;
+        }
+    }
+}
+
+impl PackageJson {
+    /// Returns the `packages` section npm would write for the manifest,
+    /// placing the `available` packages the way it does.
+    ///
+    /// Every available package needs its `name`. A dependency gets the highest
+    /// available version its range accepts, unless a version already reachable
+    /// from the dependent is accepted too. Packages are placed breadth first,
+    /// sorted by name, and their `dev` and `optional` flags are set.
+    /// Optional and peer dependencies with no available version are skipped.
+    /// Workspaces are not placed.
+    pub fn place_packages(
+        &self,
+        available: &[V2Dependency],
+    ) -> Result<Map<String, V2Dependency>, PackageLockJsonError> {
+        let mut versions = Map::<&str, Vec<(Version, usize)>>::new();
+        for (index, package) in available.iter().enumerate() {
+            let (Some(name), Ok(version)) = (package.name.as_deref(), package.version.parse())
+            else {
+                tracing::warn!(
+                    "Skipping package with no name or version: {:?}@{}",
+                    package.name,
+                    package.version
+                );
+                continue;
+            };
+            versions.entry(name).or_default().push((version, index));
+        }
+        for candidates in versions.values_mut() {
+            candidates.sort_by(|a, b| b.0.cmp(&a.0));
+        }
+
+        let root = V2Dependency {
+            name: self.name.clone(),
+            version: self.version.clone().unwrap_or_default(),
+            dependencies: self.dependencies.clone(),
+            dev_dependencies: self.dev_dependencies.clone(),
+            optional_dependencies: self.optional_dependencies.clone(),
+            peer_dependencies: self.peer_dependencies.clone(),
+            ..V2Dependency::default()
+        };
+        // the root project is the first node, then every available package.
+        let mut nodes = vec![Node::default(); available.len() + 1];
+        let mut visited = vec![false; nodes.len()];
+        let mut pending = vec![0];
+        while let Some(node) = pending.pop() {
+            let package = match node {
+                0 => &root,
+                _ => &available[node - 1],
+            };
+            let kinds = [
+                (&package.dependencies, false, false),
+                (&package.dev_dependencies, true, false),
+                (&package.optional_dependencies, false, true),
+                (&package.peer_dependencies, false, true),
+            ];
+            for (dependencies, is_dev, can_skip) in kinds {
+                if is_dev && node != 0 {
+                    // dev dependencies of packages aren't installed.
+                    continue;
+                }
+                for (name, spec) in dependencies.iter().flatten() {
+                    let mut matching = matching_versions(&versions, name, spec).map(|i| i + 1);
+                    let Some(target) = matching.next() else {
+                        if can_skip {
+                            continue;
+                        }
+                        return Err(PackageLockJsonError::PackageNotFoundError(format!(
+                            "{name}@{spec}"
+                        )));
+                    };
+                    nodes[node].dependencies.push(Dependency {
+                        name: name.clone(),
+                        node: target,
+                        alternatives: matching.collect(),
+                    });
+                    if !visited[target] {
+                        visited[target] = true;
+                        pending.push(target);
+                    }
+                }
+            }
+        }
+
+        let mut packages = Map::from([(String::new(), root)]);
+        for (path, node) in place(&nodes, 0) {
+            let mut package = available[node - 1].clone();
+            let folder_name = folder_name(&path);
+            if package.name.as_deref() == folder_name {
+                // npm only writes the name of aliased packages.
+                package.name = None;
+            }
+            packages.insert(path, package);
+        }
+        set_flags(&mut packages);
+        Ok(packages)
+    }
+}
+
+/// Returns the available packages a dependency accepts, highest version first.
+fn matching_versions<'a>(
+    versions: &'a Map<&str, Vec<(Version, usize)>>,
+    name: &str,
+    spec: &str,
+) -> impl Iterator<Item = usize> + 'a {
+    // npm:real-name@range
+    let real_name = spec
+        .strip_prefix("npm:")
+        .map(|alias| match alias.rsplit_once('@') {
+            Some((real_name, _)) if !real_name.is_empty() => real_name,
+            _ => alias,
+        })
+        .unwrap_or(name);
+    let range = spec_range(spec);
+    versions
+        .get(real_name)
+        .into_iter()
+        .flatten()
+        .filter(move |(version, _)| range.as_ref().is_none_or(|range| range.satisfies(version)))
+        .map(|(_, index)| *index)
+}
+
+impl PackageLockJson {
+    /// Returns the `packages` section [`PackageJson::place_packages`] builds
+    /// out of the root project and the installed packages of the file.
+    ///
+    /// Files with workspaces or links and v1 files are not supported.
+    pub fn canonical_packages(&self) -> Result<Map<String, V2Dependency>, PackageLockJsonError> {
+        let unsupported =
+            |reason: &str| PackageLockJsonError::UnsupportedLockfileError(reason.to_string());
+        let packages = self
+            .packages_by_path
+            .as_ref()
+            .ok_or_else(|| unsupported("v1 lock files have no layout"))?;
+        if packages
+            .iter()
+            .any(|(path, package)| package.link || !path.is_empty() && is_project_path(path))
+        {
+            return Err(unsupported("workspaces and links can't be placed"));
+        }
+
+        let root = packages.get("").cloned().unwrap_or_default();
+        let manifest = PackageJson {
+            name: root.name,
+            version: Some(root.version),
+            dependencies: root.dependencies,
+            dev_dependencies: root.dev_dependencies,
+            optional_dependencies: root.optional_dependencies,
+            peer_dependencies: root.peer_dependencies,
+            ..PackageJson::default()
+        };
+        let mut seen = HashSet::new();
+        let available = packages
+            .iter()
+            .filter_map(|(path, package)| {
+                let folder_name = folder_name(path)?;
+                let name = package.name.as_deref().unwrap_or(folder_name);
+                seen.insert((name, &package.version)).then(|| V2Dependency {
+                    name: Some(name.to_string()),
+                    ..package.clone()
+                })
+            })
+            .collect::<Vec<_>>();
+        manifest.place_packages(&available)
+    }
+
+    /// Returns where the `node_modules` layout of the file differs from
+    /// the [canonical](PackageLockJson::canonical_packages) one, sorted by path.
+    pub fn layout_differences(&self) -> Result<Vec<LayoutDifference>, PackageLockJsonError> {
+        let expected = self.canonical_packages()?;
+        let packages = self.packages_by_path.as_ref().unwrap_or(&expected);
+        let paths = packages
+            .keys()
+            .chain(expected.keys())
+            .filter(|path| !path.is_empty())
+            .collect::<BTreeSet<_>>();
+        let differences = paths
+            .into_iter()
+            .filter_map(|path| {
+                let expected = expected.get(path).map(|package| package.version.clone());
+                let actual = packages.get(path).map(|package| package.version.clone());
+                (expected != actual).then(|| LayoutDifference {
+                    path: path.clone(),
+                    expected,
+                    actual,
+                })
+            })
+            .collect();
+        Ok(differences)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{parse, parse_package_json};
+
+    fn package(name: &str, version: &str, dependencies: &[(&str, &str)]) -> V2Dependency {
+        let dependencies = dependencies
+            .iter()
+            .map(|(name, range)| (name.to_string(), range.to_string()))
+            .collect::<Map<_, _>>();
+        V2Dependency {
+            name: Some(name.to_string()),
+            version: version.to_string(),
+            dependencies: (!dependencies.is_empty()).then_some(dependencies),
+            ..V2Dependency::default()
+        }
+    }
+
+    #[test]
+    fn place_packages_works() {
+        let manifest = parse_package_json(
+            r#"{
+                "name": "app",
+                "version": "1.0.0",
+                "dependencies": { "a": "^1.0.0", "b": "^1.0.0" },
+                "devDependencies": { "c": "^1.0.0" }
+            }"#,
+        )
+        .unwrap();
+        let available = [
+            package("a", "1.0.0", &[("d", "^1.0.0")]),
+            package("b", "1.0.0", &[("d", "^2.0.0")]),
+            package("c", "1.0.0", &[("d", "^1.0.0 || ^2.0.0")]),
+            package("d", "1.0.0", &[]),
+            package("d", "2.0.0", &[]),
+            package("d", "2.1.0", &[]),
+        ];
+        let packages = manifest.place_packages(&available).unwrap();
+        let mut layout = packages
+            .iter()
+            .map(|(path, package)| (path.as_str(), package.version.as_str(), package.is_dev))
+            .collect::<Vec<_>>();
+        layout.sort();
+        assert_eq!(
+            layout,
+            [
+                ("", "1.0.0", false),
+                ("node_modules/a", "1.0.0", false),
+                ("node_modules/b", "1.0.0", false),
+                ("node_modules/b/node_modules/d", "2.1.0", false),
+                ("node_modules/c", "1.0.0", true),
+                // c takes the one already there.
+                ("node_modules/d", "1.0.0", false),
+            ]
+        );
+        assert!(packages["node_modules/a"].name.is_none());
+
+        let err = manifest.place_packages(&available[..2]);
+        assert!(matches!(
+            err,
+            Err(PackageLockJsonError::PackageNotFoundError(_))
+        ));
+    }
+
+    #[test]
+    fn layout_differences_works() {
+        // written by an older npm, placing packages in another order.
+        let content = std::fs::read_to_string("tests/v3/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap();
+        let differences = lock_file.layout_differences().unwrap();
+        assert!(differences
+            .iter()
+            .any(|difference| difference.path == "node_modules/js-tokens"));
+
+        let canonical = PackageLockJson {
+            packages_by_path: Some(lock_file.canonical_packages().unwrap()),
+            ..lock_file
+        };
+        assert_eq!(canonical.layout_differences().unwrap(), []);
+        // every package gets a version its dependents accept.
+        let packages = canonical.packages_by_path.as_ref().unwrap();
+        for path in packages.keys() {
+            for edge in graph::edges(packages, path) {
+                let Some(target) = edge.path else {
+                    assert!(
+                        edge.is_optional || edge.is_peer,
+                        "{} of {}",
+                        edge.name,
+                        path
+                    );
+                    continue;
+                };
+                let version = packages[target].version.parse().unwrap();
+                let range = spec_range(edge.spec).unwrap();
+                assert!(range.satisfies(&version), "{} of {}", edge.name, path);
+            }
+        }
+
+        let content = r#"{
+            "name": "app",
+            "lockfileVersion": 3,
+            "packages": {
+                "": { "dependencies": { "a": "^1.0.0" } },
+                "node_modules/a": { "version": "1.0.0", "dependencies": { "b": "^1.0.0" } },
+                "node_modules/a/node_modules/b": { "version": "1.0.0" }
+            }
+        }"#;
+        let differences = parse(content).unwrap().layout_differences().unwrap();
+        assert_eq!(
+            differences,
+            [
+                LayoutDifference {
+                    path: "node_modules/a/node_modules/b".to_string(),
+                    expected: None,
+                    actual: Some("1.0.0".to_string()),
+                },
+                LayoutDifference {
+                    path: "node_modules/b".to_string(),
+                    expected: Some("1.0.0".to_string()),
+                    actual: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn set_flags_handles_workspaces() {
+        let flags =
+            |package: &V2Dependency| (package.is_dev, package.is_optional, package.is_dev_optional);
+        let content = std::fs::read_to_string("tests/workspace/v3/package-lock.json").unwrap();
+        let packages = parse(content).unwrap().packages_by_path.unwrap();
+        let mut planned = packages.clone();
+        set_flags(&mut planned);
+        for (path, package) in &packages {
+            assert_eq!(flags(package), flags(&planned[path.as_str()]), "{path}");
+        }
+
+        // workspaces, their links and their production dependencies aren't dev or optional.
+        let content = std::fs::read_to_string("tests/cool-project/package-lock.json").unwrap();
+        let mut packages = parse(content).unwrap().packages_by_path.unwrap();
+        set_flags(&mut packages);
+        for path in [
+            "libs/lib2",
+            "libs/lib5/lib5-main",
+            "node_modules/@cool-project/lib2",
+            "node_modules/@lib5/main",
+            "node_modules/crypto-js",
+            "node_modules/date-fns",
+            "node_modules/uuid",
+        ] {
+            assert_eq!(flags(&packages[path]), (false, false, false), "{path}");
+        }
+        assert_eq!(
+            flags(&packages["node_modules/@types/crypto-js"]),
+            (true, false, false)
+        );
+    }
+}
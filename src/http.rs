@@ -0,0 +1,359 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use crate::{registry::PackumentSource, PackageLockJsonError, PackageName, Packument};
+
+/// Redirects followed before giving up.
+const MAX_REDIRECTS: usize = 5;
+
+/// Registry served over plain http, e.g. a local stand-in of a mirror.
+/// Packuments are requested at `<url>/<name>`, with scoped names escaped like npm does.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HttpRegistry {
+    /// Host name or ip, without brackets for ipv6.
+    pub host: String,
+    pub port: u16,
+    /// Path the registry is served under, e.g. `/npm`, empty if none.
+    pub prefix: String,
+    /// Limit for connecting and for every read and write.
+    pub timeout: Duration,
+}
+
+/// Status, lowercased headers and body of a response.
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl HttpRegistry {
+    /// Parses `http://host[:port][/prefix]`, `host` being `[::1]` like for ipv6.
+    /// https is not supported. Requests time out after 30 seconds.
+    pub fn new(url: &str) -> Result<Self, PackageLockJsonError> {
+        let (host, port, prefix) = parse_url(url)
+            .ok_or_else(|| PackageLockJsonError::RegistryError(format!("Invalid url {url}")))?;
+        let prefix = prefix.trim_end_matches('/');
+        Ok(Self {
+            host,
+            port,
+            prefix: prefix.to_string(),
+            timeout: Duration::from_secs(30),
+        })
+    }
+
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Sends a GET request, following redirects to other http urls.
+    fn get(&self, path: &str) -> Result<Response, PackageLockJsonError> {
+        let (mut host, mut port, mut path) = (self.host.clone(), self.port, path.to_string());
+        for _ in 0..=MAX_REDIRECTS {
+            let response = self.request(&host, port, &path)?;
+            if ![301, 302, 303, 307, 308].contains(&response.status) {
+                return Ok(response);
+            }
+            let location = response.header("location").ok_or_else(|| {
+                PackageLockJsonError::RegistryError(format!("Redirect without location for {path}"))
+            })?;
+            if location.starts_with('/') {
+                path = location.to_string();
+            } else {
+                (host, port, path) = parse_url(location).ok_or_else(|| {
+                    PackageLockJsonError::RegistryError(format!(
+                        "Can't follow redirect to {location}"
+                    ))
+                })?;
+            }
+        }
+        Err(PackageLockJsonError::RegistryError(format!(
+            "Too many redirects for {path}"
+        )))
+    }
+
+    fn request(&self, host: &str, port: u16, path: &str) -> Result<Response, PackageLockJsonError> {
+        let registry_error =
+            |e: std::io::Error| PackageLockJsonError::RegistryError(format!("{host}:{port}: {e}"));
+        let stream = connect(host, port, self.timeout).map_err(registry_error)?;
+        stream
+            .set_read_timeout(Some(self.timeout))
+            .and_then(|_| stream.set_write_timeout(Some(self.timeout)))
+            .map_err(registry_error)?;
+        let host_header = if host.contains(':') {
+            format!("[{host}]:{port}")
+        } else {
+            format!("{host}:{port}")
+        };
+        let request = format!(
+            "GET {path} HTTP/1.1\r\nHost: {host_header}\r\nAccept: application/json\r\nConnection: close\r\n\r\n"
+        );
+        (&stream)
+            .write_all(request.as_bytes())
+            .map_err(registry_error)?;
+        read_response(&mut BufReader::new(stream)).map_err(registry_error)
+    }
+}
+
+impl PackumentSource for HttpRegistry {
+    fn packument(&self, name: &str) -> Result<Option<Packument>, PackageLockJsonError> {
+        let name = PackageName::new(name)?;
+        let response = self.get(&format!(
+            "{}/{}",
+            self.prefix,
+            name.as_str().replace('/', "%2f")
+        ))?;
+        match response.status {
+            200 => Ok(Some(serde_json::from_slice(&response.body)?)),
+            404 => Ok(None),
+            status => Err(PackageLockJsonError::RegistryError(format!(
+                "Got {status} for {name}"
+            ))),
+        }
+    }
+}
+
+/// Parses `http://host[:port][/path]` into the host, without brackets, the port and the path.
+fn parse_url(url: &str) -> Option<(String, u16, String)> {
+    let rest = url.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(ipv6) => {
+            let (host, port) = ipv6.split_once(']')?;
+            (host, port.strip_prefix(':'))
+        }
+        None => match authority.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port.parse().ok()?,
+        None => 80,
+    };
+    (!host.is_empty()).then(|| (host.to_string(), port, path.to_string()))
+}
+
+/// Connects to the first address of the host that accepts the connection in time.
+fn connect(host: &str, port: u16, timeout: Duration) -> std::io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error
+        .unwrap_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no address found")))
+}
+
+/// Reads a response, using `Content-Length` or the chunks to know where the body ends.
+fn read_response(reader: &mut impl BufRead) -> std::io::Result<Response> {
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+    let status_line = read_line(reader)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid("invalid status line"))?;
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        let (key, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("invalid header"))?;
+        headers.push((key.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+    let mut response = Response {
+        status,
+        headers,
+        body: Vec::new(),
+    };
+    let chunked = response
+        .header("transfer-encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"));
+    if chunked {
+        loop {
+            let line = read_line(reader)?;
+            let size = line.split(';').next().unwrap_or_default().trim();
+            let size = usize::from_str_radix(size, 16).map_err(|_| invalid("invalid chunk"))?;
+            if size == 0 {
+                // trailers.
+                while !read_line(reader)?.is_empty() {}
+                break;
+            }
+            let start = response.body.len();
+            response.body.resize(start + size, 0);
+            reader.read_exact(&mut response.body[start..])?;
+            read_line(reader)?;
+        }
+    } else if let Some(length) = response.header("content-length") {
+        let length = length
+            .parse()
+            .map_err(|_| invalid("invalid content length"))?;
+        response.body.resize(length, 0);
+        reader.read_exact(&mut response.body)?;
+    } else {
+        reader.read_to_end(&mut response.body)?;
+    }
+    Ok(response)
+}
+
+fn read_line(reader: &mut impl BufRead) -> std::io::Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::{
+        io::Read,
+        net::{SocketAddr, TcpListener},
+        time::Instant,
+    };
+
+    use super::*;
+    use crate::PackumentDir;
+
+    /// Serves `tests/registry` at `/npm`, chunked, and redirects `/old` to it.
+    /// Connections are kept open, so responses have to say where they end.
+    fn serve_registry(address: &str) -> Option<SocketAddr> {
+        let listener = TcpListener::bind(address).ok()?;
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut open = Vec::new();
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let read = stream.read(&mut buffer).unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split_whitespace().nth(1).unwrap();
+                let response = if let Some(name) = path.strip_prefix("/old/") {
+                    format!("HTTP/1.1 301 Moved Permanently\r\nLocation: /npm/{name}\r\nContent-Length: 0\r\n\r\n")
+                        .into_bytes()
+                } else {
+                    let name = path.trim_start_matches("/npm/").replace("%2f", "/");
+                    match std::fs::read(format!("tests/registry/{name}.json")) {
+                        Ok(body) => {
+                            let (first, second) = body.split_at(body.len() / 2);
+                            let mut response =
+                                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+                            for chunk in [first, second] {
+                                response.extend(format!("{:x}\r\n", chunk.len()).bytes());
+                                response.extend(chunk);
+                                response.extend(b"\r\n");
+                            }
+                            response.extend(b"0\r\n\r\n");
+                            response
+                        }
+                        Err(_) => {
+                            b"HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\n\r\nnot found".to_vec()
+                        }
+                    }
+                };
+                stream.write_all(&response).unwrap();
+                open.push(stream);
+            }
+        });
+        Some(address)
+    }
+
+    fn local_packument(name: &str) -> Packument {
+        PackumentDir::new("tests/registry")
+            .packument(name)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn http_registry_works() {
+        let address = serve_registry("127.0.0.1:0").unwrap();
+        let registry = HttpRegistry::new(&format!("http://{address}/npm/"))
+            .unwrap()
+            .with_timeout(Duration::from_secs(5));
+        assert_eq!(registry.prefix, "/npm");
+        let packument = registry.packument("@scope/c").unwrap().unwrap();
+        assert_eq!(packument, local_packument("@scope/c"));
+        assert!(registry.packument("missing").unwrap().is_none());
+        assert!(matches!(
+            registry.packument("../a"),
+            Err(PackageLockJsonError::PackageNameError(_))
+        ));
+
+        let moved = HttpRegistry {
+            prefix: "/old".to_string(),
+            ..registry
+        };
+        assert_eq!(moved.packument("a").unwrap().unwrap(), local_packument("a"));
+    }
+
+    #[test]
+    fn http_registry_parses_urls() {
+        let registry = HttpRegistry::new("http://[::1]:8080/npm").unwrap();
+        assert_eq!((registry.host.as_str(), registry.port), ("::1", 8080));
+        let registry = HttpRegistry::new("http://localhost").unwrap();
+        assert_eq!((registry.port, registry.prefix.as_str()), (80, ""));
+        for invalid in [
+            "https://registry.npmjs.org",
+            "http://",
+            "http://a:b",
+            "http://[::1",
+        ] {
+            assert!(HttpRegistry::new(invalid).is_err(), "{invalid}");
+        }
+
+        // ipv6 may not be available where the tests run.
+        if let Some(address) = serve_registry("[::1]:0") {
+            let registry = HttpRegistry::new(&format!("http://{address}/npm")).unwrap();
+            assert_eq!(
+                registry.packument("a").unwrap().unwrap(),
+                local_packument("a")
+            );
+        }
+    }
+
+    #[test]
+    fn http_registry_times_out() {
+        // accepts connections and never answers.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let open = listener.incoming().collect::<Vec<_>>();
+            drop(open);
+        });
+        let registry = HttpRegistry::new(&format!("http://{address}"))
+            .unwrap()
+            .with_timeout(Duration::from_millis(200));
+        let start = Instant::now();
+        assert!(matches!(
+            registry.packument("a"),
+            Err(PackageLockJsonError::RegistryError(_))
+        ));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
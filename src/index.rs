@@ -0,0 +1,113 @@
+use std::ops::Range;
+
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use tracing::instrument;
+
+use crate::{package_from_value, Map, PackageLockJsonError, V2Dependency};
+
+/// Index over the `packages` section of a package-lock.json file.
+///
+/// The file is scanned once to record where each package is,
+/// and packages are only deserialized when asked for.
+/// v1 files have no `packages` section, so their index is empty.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PackageLockIndex {
+    pub name: String,
+    pub version: Option<String>,
+    pub lockfile_version: u32,
+    content: String,
+    offsets: Map<String, Range<usize>>,
+}
+
+#[derive(Deserialize)]
+struct RawIndex<'a> {
+    name: String,
+    version: Option<String>,
+    #[serde(rename = "lockfileVersion")]
+    lockfile_version: u32,
+    #[serde(borrow, default)]
+    packages: Option<Map<String, &'a RawValue>>,
+}
+
+impl PackageLockIndex {
+    /// Scans a package-lock.json file.
+    #[instrument(skip(content))]
+    pub fn new(content: impl Into<String> + std::fmt::Debug) -> Result<Self, PackageLockJsonError> {
+        let content = content.into();
+        let raw: RawIndex = serde_json::from_str(&content)?;
+        let start = content.as_ptr() as usize;
+        let offsets = raw
+            .packages
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(path, value)| {
+                let offset = value.get().as_ptr() as usize - start;
+                (path, offset..offset + value.get().len())
+            })
+            .collect();
+        Ok(Self {
+            name: raw.name,
+            version: raw.version,
+            lockfile_version: raw.lockfile_version,
+            offsets,
+            content,
+        })
+    }
+
+    /// Deserializes the package installed at `path`, e.g. `node_modules/react`.
+    pub fn get(&self, path: &str) -> Result<Option<V2Dependency>, PackageLockJsonError> {
+        let Some(range) = self.offsets.get(path) else {
+            return Ok(None);
+        };
+        let value = serde_json::from_str(&self.content[range.clone()])?;
+        Ok(Some(package_from_value(path, value)?))
+    }
+
+    /// Returns the install paths of every package in the file.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.offsets.keys().map(String::as_str)
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.offsets.contains_key(path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn index_works() {
+        let content = std::fs::read_to_string("tests/cool-project/package-lock.json").unwrap();
+        let index = PackageLockIndex::new(content.as_str()).unwrap();
+        assert_eq!(index.name, "cool-project");
+        assert_eq!(index.lockfile_version, 2);
+
+        let lock_file = parse(content).unwrap();
+        let packages = lock_file.packages_by_path.unwrap();
+        assert_eq!(index.len(), packages.len());
+
+        let path = "node_modules/@typescript-eslint/parser";
+        assert_eq!(index.get(path).unwrap().as_ref(), packages.get(path));
+        assert!(index.get("node_modules/missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn index_v1_is_empty() {
+        let content = std::fs::read_to_string("tests/v1/package-lock.json").unwrap();
+        let index = PackageLockIndex::new(content).unwrap();
+        assert!(index.is_empty());
+    }
+}
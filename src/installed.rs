@@ -0,0 +1,322 @@
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    name::{folder_name, is_project_path, split_install_path},
+    parse, Map, PackageLockJson, PackageLockJsonError, PackageName, SimpleDependency, V1Dependency,
+    V2Dependency,
+};
+
+/// A package installed somewhere in the project.
+/// A package installed in several folders has one entry per folder.
+#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct InstalledDependency {
+    /// Name the package is installed as.
+    pub name: PackageName,
+    /// Name of the package an alias installs, e.g. `string-width` for
+    /// `string-width-cjs` installed from `npm:string-width@^4.2.0`. `None` if it isn't one.
+    pub real_name: Option<PackageName>,
+    pub version: String,
+    /// Install path, e.g. `node_modules/a/node_modules/b`, or the folder of a workspace.
+    pub path: String,
+    pub resolved: Option<String>,
+    pub integrity: Option<String>,
+    pub is_dev: bool,
+    pub is_optional: bool,
+    pub is_dev_optional: bool,
+    pub is_in_bundle: bool,
+    pub has_install_script: bool,
+    /// Name of the workspace this package is, or is installed in.
+    pub workspace: Option<PackageName>,
+}
+
+impl From<InstalledDependency> for SimpleDependency {
+    fn from(dependency: InstalledDependency) -> Self {
+        Self {
+            name: dependency.name,
+            version: dependency.version,
+            is_dev: dependency.is_dev,
+            is_optional: dependency.is_optional,
+        }
+    }
+}
+
+impl PackageLockJson {
+    /// Returns every installed package, nested copies and workspaces included, sorted by path.
+    /// The `packages` section is used if present as it has more information than `dependencies`.
+    /// Packages with an invalid name or install path are skipped.
+    pub fn installed_dependencies(&self) -> Vec<InstalledDependency> {
+        let mut entries = Vec::new();
+        if let Some(packages) = &self.packages_by_path {
+            let workspaces = workspace_names(packages);
+            for (path, package) in packages {
+                if path.is_empty() || package.link {
+                    continue;
+                }
+                let (name, workspace) = match split_install_path(path) {
+                    Some((folder, names)) if !names.is_empty() => {
+                        let name = names.last().copied().unwrap_or_default();
+                        match PackageName::new(name) {
+                            Ok(name) => (name, workspaces.get(folder).cloned()),
+                            Err(e) => {
+                                tracing::warn!("Skipping {}: {}", path, e);
+                                continue;
+                            }
+                        }
+                    }
+                    Some(_) => {
+                        // workspaces without a valid name were already reported.
+                        let Some(name) = workspaces.get(path.as_str()) else {
+                            continue;
+                        };
+                        (name.clone(), Some(name.clone()))
+                    }
+                    None => {
+                        tracing::warn!("Skipping invalid install path {}", path);
+                        continue;
+                    }
+                };
+                entries.push(installed_v2(name, path, package, workspace));
+            }
+        } else if let Some(dependencies) = &self.dependencies {
+            add_v1_dependencies(dependencies, "", None, &mut entries);
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        entries
+    }
+}
+
+/// Returns every installed package of a package-lock.json file,
+/// nested copies and workspaces included, sorted by path.
+#[instrument(skip(content))]
+pub fn parse_installed_dependencies(
+    content: impl Into<String> + std::fmt::Debug,
+) -> Result<Vec<InstalledDependency>, PackageLockJsonError> {
+    Ok(parse(content)?.installed_dependencies())
+}
+
+fn installed_v2(
+    name: PackageName,
+    path: &str,
+    package: &V2Dependency,
+    workspace: Option<PackageName>,
+) -> InstalledDependency {
+    InstalledDependency {
+        real_name: package
+            .name
+            .as_deref()
+            .filter(|real_name| *real_name != name.as_str())
+            .and_then(|real_name| PackageName::new(real_name).ok()),
+        name,
+        version: package.version.clone(),
+        path: path.to_string(),
+        resolved: package.resolved.clone(),
+        integrity: package.integrity.clone(),
+        is_dev: package.is_dev,
+        is_optional: package.is_optional,
+        is_dev_optional: package.is_dev_optional,
+        is_in_bundle: package.is_in_bundle,
+        has_install_script: package.has_install_script,
+        workspace,
+    }
+}
+
+fn add_v1_dependencies(
+    dependencies: &Map<String, V1Dependency>,
+    prefix: &str,
+    workspace: Option<&PackageName>,
+    entries: &mut Vec<InstalledDependency>,
+) {
+    for (name, dependency) in dependencies {
+        let mut path = format!("{prefix}node_modules/{name}");
+        let name = match PackageName::new(name.as_str()) {
+            Ok(name) => name,
+            Err(e) => {
+                tracing::warn!("Skipping {}: {}", path, e);
+                continue;
+            }
+        };
+        let mut workspace = workspace.cloned();
+        if let Some(folder) = dependency.version.strip_prefix("file:") {
+            // local folders, usually workspaces.
+            path = folder.to_string();
+            workspace = Some(name.clone());
+        }
+        // aliases are written npm:real-name@1.0.0 in v1 files, v2 files split them.
+        let alias = dependency
+            .version
+            .strip_prefix("npm:")
+            .and_then(|alias| alias.rsplit_once('@'));
+        let (real_name, version) = match alias {
+            Some((real_name, version)) => (PackageName::new(real_name).ok(), version),
+            None => (None, dependency.version.as_str()),
+        };
+        entries.push(InstalledDependency {
+            name,
+            real_name,
+            version: version.to_string(),
+            path: path.clone(),
+            resolved: dependency.resolved.clone(),
+            integrity: dependency.integrity.clone(),
+            is_dev: dependency.is_dev,
+            is_optional: dependency.is_optional,
+            is_dev_optional: false,
+            is_in_bundle: dependency.bundled,
+            has_install_script: false,
+            workspace: workspace.clone(),
+        });
+        if let Some(nested) = &dependency.dependencies {
+            add_v1_dependencies(nested, &format!("{path}/"), workspace.as_ref(), entries);
+        }
+    }
+}
+
+/// Returns the name of every workspace keyed by its folder.
+/// Workspaces without a valid name are skipped.
+pub(crate) fn workspace_names(packages: &Map<String, V2Dependency>) -> Map<&str, PackageName> {
+    // the link pointing to a folder is named after the package.
+    let links = packages
+        .iter()
+        .filter(|(_, package)| package.link)
+        .filter_map(|(path, package)| {
+            let name = folder_name(path)?;
+            Some((package.resolved.as_deref()?, name))
+        })
+        .collect::<Map<_, _>>();
+    packages
+        .iter()
+        // workspaces.
+        .filter(|(path, _)| !path.is_empty() && is_project_path(path))
+        .filter_map(|(path, package)| {
+            let name = package
+                .name
+                .clone()
+                .or_else(|| links.get(path.as_str()).map(ToString::to_string))
+                .unwrap_or_else(|| path.rsplit('/').next().unwrap_or(path).to_string());
+            match PackageName::new(name) {
+                Ok(name) => Some((path.as_str(), name)),
+                Err(e) => {
+                    tracing::warn!("Skipping workspace {}: {}", path, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn installed_dependencies_keep_nested_copies() {
+        let content = std::fs::read_to_string("tests/workspace/moon/package-lock.json").unwrap();
+        let installed = parse_installed_dependencies(content).unwrap();
+        let yaml = installed
+            .iter()
+            .filter(|dependency| dependency.name == "yaml")
+            .collect::<Vec<_>>();
+        assert_eq!(yaml.len(), 2);
+        assert_eq!(yaml[0].path, "node_modules/tap/node_modules/yaml");
+        assert_eq!(yaml[0].version, "1.10.2");
+        assert!(yaml[0].is_in_bundle);
+        assert_eq!(yaml[1].path, "node_modules/yaml");
+        assert!(yaml[1].integrity.is_some());
+
+        let libnpmdiff = installed
+            .iter()
+            .find(|dependency| dependency.path == "workspaces/libnpmdiff")
+            .unwrap();
+        assert_eq!(libnpmdiff.workspace, Some("libnpmdiff".parse().unwrap()));
+    }
+
+    #[test]
+    fn installed_dependencies_in_workspaces() {
+        let content = std::fs::read_to_string("tests/cool-project/package-lock.json").unwrap();
+        let installed = parse_installed_dependencies(content).unwrap();
+        let uuid = installed
+            .iter()
+            .find(|dependency| dependency.path == "libs/telemetry/node_modules/uuid")
+            .unwrap();
+        assert_eq!(uuid.name, "uuid");
+        assert_eq!(uuid.workspace, Some("telemetry".parse().unwrap()));
+
+        // links are not installed packages.
+        assert!(!installed
+            .iter()
+            .any(|dependency| dependency.path.starts_with("node_modules/@cool-project/")));
+    }
+
+    #[test]
+    fn installed_dependencies_parse_paths() {
+        let content = r#"{
+            "name": "app",
+            "lockfileVersion": 3,
+            "packages": {
+                "": { "workspaces": ["tools/my-node_modules"] },
+                "tools/my-node_modules": { "name": "tool", "version": "1.0.0" },
+                "tools/my-node_modules/node_modules/@node_modules/a": { "version": "1.0.0" }
+            }
+        }"#;
+        let installed = parse_installed_dependencies(content).unwrap();
+        let names = installed
+            .iter()
+            .map(|dependency| {
+                (
+                    dependency.name.as_str(),
+                    dependency.workspace.as_ref().map(PackageName::as_str),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [("tool", Some("tool")), ("@node_modules/a", Some("tool"))]
+        );
+    }
+
+    #[test]
+    fn installed_dependencies_v1_works() {
+        let content = std::fs::read_to_string("tests/v1/package-lock.json").unwrap();
+        let installed = parse_installed_dependencies(content).unwrap();
+        let js_tokens = installed
+            .iter()
+            .find(|dependency| {
+                dependency.path == "node_modules/@babel/highlight/node_modules/js-tokens"
+            })
+            .unwrap();
+        assert_eq!(js_tokens.version, "4.0.0");
+        assert!(js_tokens.workspace.is_none());
+    }
+
+    #[test]
+    fn installed_dependencies_of_aliases_match() {
+        let v1 = r#"{
+            "name": "app",
+            "lockfileVersion": 1,
+            "dependencies": {
+                "string-width-cjs": {
+                    "version": "npm:string-width@4.2.3",
+                    "resolved": "https://registry.npmjs.org/string-width/-/string-width-4.2.3.tgz"
+                }
+            }
+        }"#;
+        let v2 = r#"{
+            "name": "app",
+            "lockfileVersion": 3,
+            "packages": {
+                "": {},
+                "node_modules/string-width-cjs": {
+                    "name": "string-width",
+                    "version": "4.2.3",
+                    "resolved": "https://registry.npmjs.org/string-width/-/string-width-4.2.3.tgz"
+                }
+            }
+        }"#;
+        let v1 = parse_installed_dependencies(v1).unwrap();
+        let v2 = parse_installed_dependencies(v2).unwrap();
+        assert_eq!(v1, v2);
+        assert_eq!(v1[0].version, "4.2.3");
+        assert_eq!(v1[0].real_name, Some("string-width".parse().unwrap()));
+    }
+}
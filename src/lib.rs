@@ -1,82 +1,411 @@
-use std::collections::HashMap;
+use std::{
+    borrow::Borrow,
+    fs::File,
+    hash::Hash,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing::instrument;
 
+mod borrowed;
+mod convert;
+mod deprecated;
+mod duplicates;
+mod edit;
+mod engines;
+mod extract;
+mod graph;
+mod hoist;
+#[cfg(feature = "http")]
+mod http;
+mod index;
+mod installed;
+mod lockfile;
+#[cfg(feature = "rayon")]
+mod many;
+mod merge;
+mod name;
+mod outdated;
+mod overrides;
+mod package_json;
+mod platform;
+mod prune;
+mod purl;
+mod registry;
+mod semver;
+mod upgrade;
+
+pub use borrowed::{parse_borrowed, PackageLockJsonRef, V1DependencyRef, V2DependencyRef};
+pub use deprecated::{DeprecatedPackage, DeprecationGroup};
+pub use duplicates::{Dependent, DuplicatePackage};
+pub use engines::{EngineIncompatibility, EngineRange, Engines, LegacyEngines};
+pub use hoist::LayoutDifference;
+#[cfg(feature = "http")]
+pub use http::HttpRegistry;
+pub use index::PackageLockIndex;
+pub use installed::{parse_installed_dependencies, InstalledDependency};
+pub use lockfile::{detect_and_parse, Lockfile, LockfileKind, Workspace};
+#[cfg(feature = "rayon")]
+pub use many::{parse_many, parse_many_files};
+pub use merge::{merge, merge_conflicted, MergeConflict, MergedLockFile};
+use name::is_project_path;
+pub use name::PackageName;
+pub use outdated::OutdatedPackage;
+pub use overrides::{OverrideRule, OverrideViolation, Overrides};
+pub use package_json::{
+    parse_package_json, Bin, BundleDependencies, LockfileMismatch, PackageJson, PeerDependencyMeta,
+    Workspaces,
+};
+pub use platform::Platform;
+pub use purl::Purl;
+pub use registry::{Dist, Packument, PackumentDir, PackumentSource, PackumentVersion};
+pub use semver::{Prerelease, Version, VersionRange};
+pub use upgrade::{PackageChange, UpgradePlan};
+
+/// Map used throughout the model.
+/// With the `preserve_order` feature, entries keep the order they have in the file.
+#[cfg(not(feature = "preserve_order"))]
+pub type Map<K, V> = std::collections::HashMap<K, V>;
+/// Map used throughout the model.
+/// With the `preserve_order` feature, entries keep the order they have in the file.
+#[cfg(feature = "preserve_order")]
+pub type Map<K, V> = indexmap::IndexMap<K, V>;
+
 #[derive(Debug, Error)]
 #[error("package-lock.json error")]
 pub enum PackageLockJsonError {
     #[error("Error parsing file: {0}")]
     ParseError(#[from] serde_json::Error),
+    #[error("Error reading file {}: {source}", path.display())]
+    IoError {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Error parsing file {}: {source}", path.display())]
+    FileParseError {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Unsupported lock file: {0}")]
+    UnsupportedLockfileError(String),
+    #[error("Invalid version or range: {0}")]
+    SemverError(String),
+    #[error("Invalid overrides: {0}")]
+    OverridesError(String),
+    #[error("Invalid platform: {0}")]
+    PlatformError(String),
+    #[error("Package not found: {0}")]
+    PackageNotFoundError(String),
+    #[error("Workspace not found: {0}")]
+    WorkspaceNotFoundError(String),
+    #[error("Invalid conflict markers: {0}")]
+    ConflictMarkersError(String),
+    #[error("Can't upgrade: {0}")]
+    UpgradeError(String),
+    #[error("Registry error: {0}")]
+    RegistryError(String),
+    #[error("Invalid purl: {0}")]
+    PurlError(String),
+    #[error("Invalid package name: {0}")]
+    PackageNameError(String),
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone, Eq, PartialEq)]
+#[serde(from = "RawPackageLockJson")]
 pub struct PackageLockJson {
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub version: Option<String>,
     #[serde(rename = "lockfileVersion")]
     pub lockfile_version: u32,
-    pub dependencies: Option<HashMap<String, V1Dependency>>,
-    #[serde(deserialize_with = "deserialize_packages", default)]
-    pub packages: Option<HashMap<String, V2Dependency>>,
+    /// Top level packages keyed by package name.
+    /// Nested packages, links and the root project are not included.
+    #[serde(skip_serializing)]
+    pub packages: Option<Map<PackageName, V2Dependency>>,
+    /// Every entry of the `packages` section keyed by its install path,
+    /// e.g. `""` for the root project or `node_modules/a/node_modules/b`.
+    #[serde(
+        rename = "packages",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub packages_by_path: Option<Map<String, V2Dependency>>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub dependencies: Option<Map<String, V1Dependency>>,
+    /// Fields not modelled above, e.g. `requires`, kept so they are written back.
+    #[serde(flatten, serialize_with = "serialize_extra")]
+    pub extra: Map<String, serde_json::Value>,
+}
+
+/// Shape of the file on disk. `packages` is kept as is and
+/// the name keyed view is derived from it.
+#[derive(Deserialize)]
+struct RawPackageLockJson {
+    name: String,
+    version: Option<String>,
+    #[serde(rename = "lockfileVersion")]
+    lockfile_version: u32,
+    dependencies: Option<Map<String, V1Dependency>>,
+    #[serde(deserialize_with = "deserialize_package_paths", default)]
+    packages: Option<Map<String, V2Dependency>>,
+    #[serde(flatten)]
+    extra: Map<String, serde_json::Value>,
+}
+
+impl From<RawPackageLockJson> for PackageLockJson {
+    fn from(raw: RawPackageLockJson) -> Self {
+        Self {
+            name: raw.name,
+            version: raw.version,
+            lockfile_version: raw.lockfile_version,
+            packages: raw.packages.as_ref().map(normalize_packages),
+            packages_by_path: raw.packages,
+            dependencies: raw.dependencies,
+            extra: raw.extra,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Default)]
 pub struct V1Dependency {
     pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub resolved: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub integrity: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_false")]
     pub bundled: bool,
-    #[serde(rename = "dev", default)]
+    #[serde(rename = "dev", default, skip_serializing_if = "is_false")]
     pub is_dev: bool,
-    #[serde(rename = "optional", default)]
+    #[serde(rename = "optional", default, skip_serializing_if = "is_false")]
     pub is_optional: bool,
-    pub requires: Option<HashMap<String, String>>,
-    pub dependencies: Option<HashMap<String, V1Dependency>>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub requires: Option<Map<String, String>>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub dependencies: Option<Map<String, V1Dependency>>,
+    /// Fields not modelled above, kept so they are written back.
+    #[serde(flatten, serialize_with = "serialize_extra")]
+    pub extra: Map<String, serde_json::Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Default)]
 pub struct V2Dependency {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub resolved: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub integrity: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_false")]
     pub bundled: bool,
-    #[serde(rename = "dev", default)]
+    #[serde(rename = "dev", default, skip_serializing_if = "is_false")]
     pub is_dev: bool,
-    #[serde(rename = "optional", default)]
+    #[serde(rename = "optional", default, skip_serializing_if = "is_false")]
     pub is_optional: bool,
-    #[serde(rename = "devOptional", default)]
+    #[serde(rename = "devOptional", default, skip_serializing_if = "is_false")]
     pub is_dev_optional: bool,
-    #[serde(rename = "inBundle", default)]
+    #[serde(rename = "inBundle", default, skip_serializing_if = "is_false")]
     pub is_in_bundle: bool,
-    #[serde(rename = "hasInstallScript", default)]
+    #[serde(rename = "hasInstallScript", default, skip_serializing_if = "is_false")]
     pub has_install_script: bool,
-    #[serde(rename = "hasShrinkwrap", default)]
+    #[serde(rename = "hasShrinkwrap", default, skip_serializing_if = "is_false")]
     pub has_shrink_wrap: bool,
-    pub dependencies: Option<HashMap<String, String>>,
-    #[serde(rename = "devDependencies")]
-    pub dev_dependencies: Option<HashMap<String, String>>,
-    #[serde(rename = "optionalDependencies")]
-    pub optional_dependencies: Option<HashMap<String, String>>,
-    #[serde(rename = "peerDependencies")]
-    pub peer_dependencies: Option<HashMap<String, String>>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub dependencies: Option<Map<String, String>>,
+    #[serde(
+        rename = "devDependencies",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub dev_dependencies: Option<Map<String, String>>,
+    #[serde(
+        rename = "optionalDependencies",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub optional_dependencies: Option<Map<String, String>>,
+    #[serde(
+        rename = "peerDependencies",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub peer_dependencies: Option<Map<String, String>>,
+    #[serde(
+        rename = "peerDependenciesMeta",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub peer_dependencies_meta: Option<Map<String, PeerDependencyMeta>>,
+    /// Ranges accepted besides the declared ones, written by `npm install --force`.
+    #[serde(
+        rename = "acceptDependencies",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub accept_dependencies: Option<Map<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub license: Option<String>,
-    pub engines: Option<HashMap<String, String>>,
-    pub bin: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub engines: Option<Engines>,
+    /// Operating systems the package runs on, e.g. `darwin` or `!win32`.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_string_list",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub os: Option<Vec<String>>,
+    /// Architectures the package runs on, e.g. `x64` or `!arm`.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_string_list",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub cpu: Option<Vec<String>>,
+    /// C libraries the package runs on in linux, `glibc` or `musl`.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_string_list",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub libc: Option<Vec<String>>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub bin: Option<Map<String, String>>,
+    /// Deprecation message of the installed version.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub funding: Option<Funding>,
+    /// Only set on the root project entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspaces: Option<Vec<String>>,
+    /// Not required by any other package.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub extraneous: bool,
+    /// Symlink to a folder in the project, typically a workspace.
+    /// `resolved` holds the path of the target folder.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub link: bool,
+    /// Fields not modelled above, kept so they are written back.
+    #[serde(flatten, serialize_with = "serialize_extra")]
+    pub extra: Map<String, serde_json::Value>,
+}
+
+/// Where to fund a package: a url, a source or a list of them.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum Funding {
+    Url(String),
+    Source(FundingSource),
+    List(Vec<Funding>),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct FundingSource {
+    /// e.g. `github` or `opencollective`.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    pub url: String,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct SimpleDependency {
-    pub name: String,
+    pub name: PackageName,
     pub version: String,
     pub is_dev: bool,
     pub is_optional: bool,
 }
 
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// Accepts a list of strings or a single one.
+fn deserialize_string_list<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringList {
+        One(String),
+        Many(Vec<String>),
+    }
+    let list: Option<StringList> = Deserialize::deserialize(deserializer)?;
+    Ok(list.map(|list| match list {
+        StringList::One(value) => vec![value],
+        StringList::Many(values) => values,
+    }))
+}
+
+/// Writes maps sorted by key so the output is stable.
+#[cfg(not(feature = "preserve_order"))]
+fn serialize_sorted<S, V>(map: &Option<Map<String, V>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    V: Serialize,
+{
+    map.as_ref()
+        .map(|map| map.iter().collect::<std::collections::BTreeMap<_, _>>())
+        .serialize(serializer)
+}
+
+/// Removes an entry, keeping the order of the others.
+#[cfg(not(feature = "preserve_order"))]
+fn remove_entry<K: Borrow<str> + Hash + Eq, V>(map: &mut Map<K, V>, key: &str) -> Option<V> {
+    map.remove(key)
+}
+
+/// Removes an entry, keeping the order of the others.
+#[cfg(feature = "preserve_order")]
+fn remove_entry<K: Borrow<str> + Hash + Eq, V>(map: &mut Map<K, V>, key: &str) -> Option<V> {
+    map.shift_remove(key)
+}
+
+/// Writes flattened unknown fields, sorted by key unless the order is preserved.
+fn serialize_extra<S>(
+    extra: &Map<String, serde_json::Value>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let mut fields = extra.iter().collect::<Vec<_>>();
+    if cfg!(not(feature = "preserve_order")) {
+        fields.sort_by_key(|(key, _)| *key);
+    }
+    serializer.collect_map(fields)
+}
+
+/// Maps already keep the order of the file, so they are written as they are.
+#[cfg(feature = "preserve_order")]
+fn serialize_sorted<S, V>(map: &Option<Map<String, V>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    V: Serialize,
+{
+    map.serialize(serializer)
+}
+
 /// Parses a package-lock.json file.
 /// Support v1, v2 and v3 lock files
 #[instrument(skip(content))]
@@ -84,129 +413,178 @@ pub fn parse(
     content: impl Into<String> + std::fmt::Debug,
 ) -> Result<PackageLockJson, PackageLockJsonError> {
     let mut json: PackageLockJson = serde_json::from_str(&content.into())?;
-    // fix version for v2 and workspaces
-    // version = "file:mainlib" -> version = "0.0.0"
+    fix_file_versions(&mut json);
+    Ok(json)
+}
+
+/// Parses a package-lock.json file from a reader.
+/// Support v1, v2 and v3 lock files
+///
+/// The reader is not buffered, wrap it in a [`std::io::BufReader`] if needed.
+#[instrument(skip(reader))]
+pub fn parse_reader(reader: impl Read) -> Result<PackageLockJson, PackageLockJsonError> {
+    let mut json: PackageLockJson = serde_json::from_reader(reader)?;
+    fix_file_versions(&mut json);
+    Ok(json)
+}
+
+/// Reads and parses a package-lock.json file.
+/// Support v1, v2 and v3 lock files
+///
+/// Errors contain the path of the file.
+#[instrument(skip_all, fields(path = %path.as_ref().display()))]
+pub fn parse_file(path: impl AsRef<Path>) -> Result<PackageLockJson, PackageLockJsonError> {
+    let path = path.as_ref();
+    let into_error = |source| PackageLockJsonError::IoError {
+        path: path.to_path_buf(),
+        source,
+    };
+    let file = File::open(path).map_err(into_error)?;
+    parse_reader(BufReader::new(file)).map_err(|e| match e {
+        PackageLockJsonError::ParseError(e) if e.is_io() => into_error(e.into()),
+        PackageLockJsonError::ParseError(source) => PackageLockJsonError::FileParseError {
+            path: path.to_path_buf(),
+            source,
+        },
+        e => e,
+    })
+}
+
+/// Fixes the version of v1 dependencies for v2 and workspaces.
+/// version = "file:mainlib" -> version = "0.0.0"
+fn fix_file_versions(json: &mut PackageLockJson) {
     if let (Some(dependencies), Some(packages)) =
         (json.dependencies.as_mut(), json.packages.as_ref())
     {
         for (name, dependency) in dependencies {
             if dependency.version.starts_with("file:") {
-                if let Some(pkg) = packages.get(name) {
+                if let Some(pkg) = packages.get(name.as_str()) {
                     dependency.version = pkg.version.clone();
                 }
             }
         }
     }
-    Ok(json)
 }
 
 /// Returns a list of dependencies from a package-lock.json file.
+/// Packages installed in several folders appear once per folder.
 /// The dependencies returned by this function only show a few fields.
-/// If you need more information, use the parse function.
+/// If you need more information, use the parse or the parse_installed_dependencies functions.
 #[instrument(skip(content))]
 pub fn parse_dependencies(
     content: impl Into<String> + std::fmt::Debug,
 ) -> Result<Vec<SimpleDependency>, PackageLockJsonError> {
     let json = parse(content)?;
-    let mut entries = Vec::new();
-    if let Some(dependencies) = json.dependencies {
-        for (name, dependency) in dependencies {
-            entries.push(SimpleDependency {
-                name,
-                version: dependency.version,
-                is_dev: dependency.is_dev,
-                is_optional: dependency.is_optional,
-            });
-        }
-    } else if let Some(packages) = json.packages {
-        for (name, dependency) in packages {
-            entries.push(SimpleDependency {
-                name,
-                version: dependency.version,
-                is_dev: dependency.is_dev,
-                is_optional: dependency.is_optional,
-            });
-        }
-    }
+    let entries = json
+        .installed_dependencies()
+        .into_iter()
+        .map(SimpleDependency::from)
+        .collect();
     Ok(entries)
 }
 
-fn deserialize_packages<'de, D>(
+fn deserialize_package_paths<'de, D>(
     deserializer: D,
-) -> Result<Option<HashMap<String, V2Dependency>>, D::Error>
+) -> Result<Option<Map<String, V2Dependency>>, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    let value: Option<HashMap<String, serde_json::Value>> =
+    let value: Option<Map<String, serde_json::Value>> =
         serde::Deserialize::deserialize(deserializer)?;
-    if let Some(package) = value {
-        let mut packages = HashMap::new();
-        for (key, mut value) in package {
-            if key.is_empty() {
-                // skipping package information as it doesn't follow the schema.
-                tracing::info!("Skipping package information in packages.");
-                continue;
-            }
-            // check for engine bad formats.
-            // some people use an array instead of an object.
-            if let Some(engines) = value.get("engines").and_then(serde_json::Value::as_array) {
-                tracing::warn!(
-                    "Found engines as an array instead of an object. Fixing it. ({})",
-                    key
-                );
-                if engines.is_empty() {
-                    value["engines"] = serde_json::Value::Null;
-                } else {
-                    let mut new_engines = HashMap::new();
-                    for engine in engines {
-                        let engine = engine.as_str().unwrap();
-                        let (name, version) =
-                            engine.split_once(' ').unwrap_or(("not_found", "not_found"));
-                        new_engines.insert(name, version);
-                    }
-                    value["engines"] = serde_json::value::to_value(new_engines).unwrap();
-                }
+    let Some(package) = value else {
+        return Ok(None);
+    };
+    let parse_entry = |(key, value): (String, serde_json::Value)| {
+        match package_from_value(&key, value) {
+            Ok(package) => Some((key, package)),
+            Err(e) => {
+                // swallowing the error as we don't want to break the whole process
+                // let's just log the error:
+                tracing::error!("Could not parse this dependency: {}, ERROR: {}", key, e);
+                None
             }
+        }
+    };
+    #[cfg(feature = "rayon")]
+    let packages = {
+        use rayon::prelude::*;
+        package.into_par_iter().filter_map(parse_entry).collect()
+    };
+    #[cfg(not(feature = "rayon"))]
+    let packages = package.into_iter().filter_map(parse_entry).collect();
+    Ok(Some(packages))
+}
 
-            let vclone = value.clone();
-
-            let package = serde_json::from_value::<V2Dependency>(value);
-            match package {
-                Ok(package) => {
-                    let pattern = "node_modules/";
-                    if key.starts_with(pattern) {
-                        if !key.contains("/node_modules/") {
-                            // we are ignoring nested dependencies
-                            let key = key.replace(pattern, "");
-                            packages.insert(key, package);
-                        }
-                    } else {
-                        // possibly workspaces, let's look for name
-                        if let Some(ref name) = package.name {
-                            // if name, we will use it as the key.
-                            // these packages will also have a version with a `node_modules/` prefix.
-                            // as that version won't have a version, it will fail to parse and will be silently ignored.
-                            packages.insert(name.clone(), package);
-                        } else {
-                            packages.insert(key, package);
-                        }
-                    }
-                }
-                Err(e) => {
-                    // swallowing the error as we don't want to break the whole process
-                    // let's just log the error:
-                    tracing::error!(
-                        "Could not parse this dependency: {:?}, ERROR: {}",
-                        vclone,
-                        e
-                    );
-                    continue;
-                }
-            };
+/// Deserializes an entry of the `packages` section.
+fn package_from_value(
+    key: &str,
+    value: serde_json::Value,
+) -> Result<V2Dependency, serde_json::Error> {
+    let package: V2Dependency = serde_json::from_value(value)?;
+    if let Some(engines) = &package.engines {
+        // some people use an array instead of an object.
+        if let Some(form) = engines.repaired_from {
+            tracing::warn!(
+                "Found engines as {:?} instead of an object. Fixing it. ({})",
+                form,
+                key
+            );
+        }
+        for diagnostic in &engines.diagnostics {
+            tracing::warn!("{} ({})", diagnostic, key);
+        }
+    }
+    Ok(package)
+}
+
+/// Builds the name keyed view of the `packages` section.
+fn normalize_packages(
+    packages_by_path: &Map<String, V2Dependency>,
+) -> Map<PackageName, V2Dependency> {
+    let mut packages = Map::new();
+    for (key, package) in packages_by_path {
+        if let Some(name) = package_key(key, package) {
+            packages.insert(name, package.clone());
+        }
+    }
+    packages
+}
+
+/// Returns the key of the package installed at `path` in the name keyed view of `packages`,
+/// `None` if it isn't part of it.
+pub(crate) fn package_key(path: &str, package: &V2Dependency) -> Option<PackageName> {
+    if path.is_empty() {
+        // the root project isn't a package of the view.
+        return None;
+    }
+    if package.link || package.version.is_empty() {
+        // links point to a workspace folder that has its own entry.
+        return None;
+    }
+    if path.starts_with("node_modules/") {
+        match PackageName::from_install_path(path) {
+            // we are ignoring nested dependencies
+            Ok(mut names) if names.len() == 1 => names.pop(),
+            Ok(_) => None,
+            Err(e) => {
+                tracing::warn!("Skipping {}: {}", path, e);
+                None
+            }
         }
-        Ok(Some(packages))
+    } else if !is_project_path(path) {
+        // installed in a workspace, like nested dependencies.
+        None
     } else {
-        Ok(None)
+        // possibly workspaces, keyed by their name, or by their folder like npm does.
+        // these packages will also have a link with a `node_modules/` prefix,
+        // which is skipped above.
+        let name = package
+            .name
+            .clone()
+            .unwrap_or_else(|| path.rsplit('/').next().unwrap_or(path).to_string());
+        PackageName::new(name)
+            .inspect_err(|e| tracing::warn!("Skipping {}: {}", path, e))
+            .ok()
     }
 }
 
@@ -223,8 +601,8 @@ mod tests {
             bundled: false,
             is_dev: true,
             is_optional: false,
-            requires: Some(HashMap::from([("js-tokens".to_string(), "^4.0.0".to_string()), ("chalk".to_string(), "^2.0.0".to_string()),("@babel/helper-validator-identifier".to_string(), "^7.18.6".to_string())])),
-            dependencies: Some(HashMap::from([("js-tokens".to_string(), V1Dependency {
+            requires: Some(Map::from([("js-tokens".to_string(), "^4.0.0".to_string()), ("chalk".to_string(), "^2.0.0".to_string()),("@babel/helper-validator-identifier".to_string(), "^7.18.6".to_string())])),
+            dependencies: Some(Map::from([("js-tokens".to_string(), V1Dependency {
                 version: "4.0.0".to_string(),
                 resolved: Some("https://registry.npmjs.org/js-tokens/-/js-tokens-4.0.0.tgz".to_string()),
                 integrity: Some("sha512-RdJUflcE3cUzKiMqQgsCu06FPu9UdIJO0beYbPhHN4k6apgJtifcoCtT9bcxOpYBtpD2kCM6Sbzg4CausW/PKQ==".to_string()),
@@ -232,7 +610,8 @@ mod tests {
                 bundled: false,
                 ..V1Dependency::default()
                 })]
-            ))
+            )),
+            ..V1Dependency::default()
         }
     }
 
@@ -244,8 +623,8 @@ mod tests {
             bundled: false,
             is_dev: true,
             is_optional: false,
-            dependencies: Some(HashMap::from([("js-tokens".to_string(), "^4.0.0".to_string()), ("chalk".to_string(), "^2.0.0".to_string()),("@babel/helper-validator-identifier".to_string(), "^7.18.6".to_string())])),
-            engines: Some(HashMap::from([("node".to_string(), ">=6.9.0".to_string())])),
+            dependencies: Some(Map::from([("js-tokens".to_string(), "^4.0.0".to_string()), ("chalk".to_string(), "^2.0.0".to_string()),("@babel/helper-validator-identifier".to_string(), "^7.18.6".to_string())])),
+            engines: Some(Engines::from_iter([("node", ">=6.9.0")])),
             ..V2Dependency::default()
         }
     }
@@ -298,13 +677,13 @@ mod tests {
             resolved: Some("https://registry.npmjs.org/yaml/-/yaml-2.2.2.tgz".to_string()),
             integrity: Some("sha512-CBKFWExMn46Foo4cldiChEzn7S7SRV+wqiluAb6xmueD/fGyRHIhX8m14vVGgeFWjN540nKCNVj6P21eQjgTuA==".to_string()),
             is_dev: true,
-            engines: Some(HashMap::from([("node".to_string(), ">= 14".to_string())])),
+            engines: Some(Engines::from_iter([("node", ">= 14")])),
             ..V2Dependency::default()
         };
         assert_eq!(yaml, &expected_yaml);
 
         // workspace?
-        let libnpmdiff = packages.get("workspaces/libnpmdiff").unwrap();
+        let libnpmdiff = packages.get("libnpmdiff").unwrap();
         assert_eq!(libnpmdiff.version, "5.0.17".to_string());
         assert_eq!(libnpmdiff.license, Some("ISC".to_string()));
         assert!(libnpmdiff.dependencies.is_some());
@@ -330,7 +709,7 @@ mod tests {
         let expected_base = V2Dependency {
             version: "1.0.0".to_string(),
             name: Some("test-node-npm-base".to_string()),
-            dependencies: Some(HashMap::from([("react".to_string(), "17.0.0".to_string())])),
+            dependencies: Some(Map::from([("react".to_string(), "17.0.0".to_string())])),
             ..V2Dependency::default()
         };
         assert_eq!(test_node_npm_base, &expected_base);
@@ -346,7 +725,7 @@ mod tests {
             test_node_npm_v1,
             &V1Dependency {
                 version: "1.0.0".to_string(),
-                requires: Some(HashMap::from([("react".to_string(), "17.0.0".to_string())])),
+                requires: Some(Map::from([("react".to_string(), "17.0.0".to_string())])),
                 ..V1Dependency::default()
             }
         );
@@ -374,7 +753,7 @@ mod tests {
             bundled: false,
             is_dev: false,
             is_optional: false,
-            dependencies: Some(HashMap::from([("libb2".to_string(), "*".to_string())])),
+            dependencies: Some(Map::from([("libb2".to_string(), "*".to_string())])),
             license: Some("ISC".to_string()),
             engines: None,
             ..V2Dependency::default()
@@ -468,6 +847,84 @@ mod tests {
         assert_eq!(babel_highlight, &expected);
     }
 
+    #[test]
+    fn parse_file_works() {
+        let lock_file = parse_file("tests/v3/package-lock.json").unwrap();
+        let content = std::fs::read_to_string("tests/v3/package-lock.json").unwrap();
+        assert_eq!(lock_file, parse(content).unwrap());
+
+        let err = parse_file("tests/missing/package-lock.json").unwrap_err();
+        assert!(
+            matches!(err, PackageLockJsonError::IoError { ref path, .. } if path.ends_with("missing/package-lock.json"))
+        );
+
+        // parse errors keep the path too.
+        let err = parse_file("tests/package.json").unwrap_err();
+        assert!(err.to_string().contains("tests/package.json"));
+        assert!(
+            matches!(err, PackageLockJsonError::FileParseError { ref path, ref source } if path.ends_with("tests/package.json") && source.is_data())
+        );
+
+        // so do syntax errors.
+        let path = std::env::temp_dir().join("package-lock-json-parser-invalid.json");
+        std::fs::write(&path, "{ name: 1 }").unwrap();
+        let err = parse_file(&path).unwrap_err();
+        assert!(
+            matches!(err, PackageLockJsonError::FileParseError { ref source, .. } if source.is_syntax())
+        );
+    }
+
+    #[test]
+    fn parse_reader_works() {
+        let content = std::fs::read_to_string("tests/cool-project/package-lock.json").unwrap();
+        let lock_file = parse_reader(content.as_bytes()).unwrap();
+        assert_eq!(lock_file, parse(content).unwrap());
+    }
+
+    #[cfg(feature = "preserve_order")]
+    #[test]
+    fn preserve_order_works() {
+        let content = std::fs::read_to_string("tests/v2/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap();
+        let paths = lock_file
+            .packages_by_path
+            .as_ref()
+            .unwrap()
+            .keys()
+            .take(3)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "",
+                "node_modules/@babel/code-frame",
+                "node_modules/@babel/helper-validator-identifier"
+            ]
+        );
+
+        // written in the order of the file, not sorted.
+        let content = std::fs::read_to_string("tests/cool-project/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap();
+        let json = lock_file.to_json().unwrap();
+        assert!(json.find("\"mainlib\": {").unwrap() < json.find("\"libs/lib1\": {").unwrap());
+
+        // nested maps keep their order too.
+        let packages = lock_file.packages_by_path.as_ref().unwrap();
+        let dependencies = packages["libs/lib4"].dependencies.as_ref().unwrap();
+        assert_eq!(
+            dependencies.keys().take(2).collect::<Vec<_>>(),
+            ["@lib5/secondary", "@cool-project/lib1"]
+        );
+        let value = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        let dependencies = value["packages"]["libs/lib4"]["dependencies"]
+            .as_object()
+            .unwrap();
+        assert_eq!(
+            dependencies.keys().take(2).collect::<Vec<_>>(),
+            ["@lib5/secondary", "@cool-project/lib1"]
+        );
+    }
+
     #[test]
     fn deserialize_packages_works() {
         let content = r#"{
@@ -483,15 +940,34 @@ mod tests {
         }"#;
 
         let mut deserializer = serde_json::Deserializer::from_str(content);
-        let packages = deserialize_packages(&mut deserializer).unwrap().unwrap();
+        let packages_by_path = deserialize_package_paths(&mut deserializer)
+            .unwrap()
+            .unwrap();
+        let packages = normalize_packages(&packages_by_path);
         // removes node_modules/ from the key
         let package = packages.get("extsprintf").unwrap();
         assert_eq!(package.version, "1.3.0");
         assert!(package.is_dev);
-        assert_eq!(
-            package.engines,
-            Some(HashMap::from([("node".to_string(), ">=0.6.0".to_string())]))
-        );
+        let engines = package.engines.as_ref().unwrap();
+        assert_eq!(engines.repaired_from, Some(LegacyEngines::Array));
+        assert_eq!(engines.ranges["node"].raw, ">=0.6.0");
+    }
+
+    #[test]
+    fn normalize_packages_parses_names() {
+        let content = r#"{
+            "name": "app",
+            "lockfileVersion": 3,
+            "packages": {
+                "node_modules/@node_modules/a": { "version": "1.0.0" },
+                "node_modules/@node_modules/a/node_modules/b": { "version": "1.0.0" },
+                "node_modules/@scope": { "version": "1.0.0" }
+            }
+        }"#;
+        let packages = parse(content).unwrap().packages.unwrap();
+        // nested packages and invalid names are skipped.
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages["@node_modules/a"].version, "1.0.0");
     }
 
     #[test]
@@ -507,6 +983,78 @@ mod tests {
         assert!(!first.is_optional);
     }
 
+    #[test]
+    fn parse_entries_keep_nested_duplicates() {
+        let content = std::fs::read_to_string("tests/workspace/moon/package-lock.json").unwrap();
+        let dependencies = parse_dependencies(content).unwrap();
+        let yaml = dependencies
+            .iter()
+            .filter(|dependency| dependency.name == "yaml")
+            .count();
+        assert_eq!(yaml, 2);
+    }
+
+    #[test]
+    fn parse_package_metadata_works() {
+        let content = std::fs::read_to_string("tests/cool-project/package-lock.json").unwrap();
+        let packages = parse(content).unwrap().packages_by_path.unwrap();
+        let fsevents = &packages["node_modules/fsevents"];
+        assert_eq!(fsevents.os, Some(vec!["darwin".to_string()]));
+        assert!(fsevents.is_optional);
+
+        let htmlparser2 = &packages["node_modules/htmlparser2"];
+        let Some(Funding::List(funding)) = &htmlparser2.funding else {
+            panic!("funding should be a list");
+        };
+        assert!(matches!(funding[0], Funding::Url(_)));
+        assert!(
+            matches!(&funding[1], Funding::Source(source) if source.kind.as_deref() == Some("github"))
+        );
+
+        let ws = &packages["node_modules/ws"];
+        assert!(ws.peer_dependencies_meta.as_ref().unwrap()["bufferutil"].optional);
+        let sourcemap_codec = &packages["node_modules/sourcemap-codec"];
+        assert_eq!(
+            sourcemap_codec.deprecated.as_deref(),
+            Some("Please use @jridgewell/sourcemap-codec instead")
+        );
+    }
+
+    #[test]
+    fn unknown_fields_round_trip() {
+        let content = r#"{
+            "name": "future",
+            "lockfileVersion": 3,
+            "requires": true,
+            "packages": {
+                "node_modules/a": {
+                    "version": "1.0.0",
+                    "futureField": { "enabled": true }
+                }
+            },
+            "dependencies": {
+                "a": { "version": "1.0.0", "futureFlag": 1 }
+            }
+        }"#;
+        let mut lock_file = parse(content).unwrap();
+        assert_eq!(lock_file.extra["requires"], serde_json::Value::Bool(true));
+        let packages = lock_file.packages_by_path.as_mut().unwrap();
+        let a = packages.get_mut("node_modules/a").unwrap();
+        assert_eq!(
+            a.extra["futureField"]["enabled"],
+            serde_json::Value::Bool(true)
+        );
+        a.version = "1.0.1".to_string();
+
+        let json = lock_file.to_json().unwrap();
+        let reparsed = parse(json.as_str()).unwrap();
+        assert_eq!(reparsed.to_json().unwrap(), json);
+        assert_eq!(reparsed.packages_by_path, lock_file.packages_by_path);
+        assert_eq!(reparsed.extra, lock_file.extra);
+        let a = &reparsed.dependencies.as_ref().unwrap()["a"];
+        assert_eq!(a.extra["futureFlag"], serde_json::json!(1));
+    }
+
     #[test]
     fn parse_entries_v2_works() {
         let content = std::fs::read_to_string("tests/v3/package-lock.json").unwrap();
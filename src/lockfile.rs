@@ -0,0 +1,334 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use tracing::instrument;
+
+use crate::{
+    installed::workspace_names, parse, parse_file, Map, PackageLockJson, PackageLockJsonError,
+    PackageName, SimpleDependency, V1Dependency,
+};
+
+/// Format of a parsed lock file.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum LockfileKind {
+    /// `package-lock.json` or `npm-shrinkwrap.json`.
+    Npm { lockfile_version: u32 },
+}
+
+/// A workspace of a monorepo.
+#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Hash)]
+pub struct Workspace {
+    pub name: PackageName,
+    /// Folder of the workspace, relative to the root project.
+    pub path: String,
+    pub version: Option<String>,
+}
+
+/// Common view over lock files, whatever the package manager that wrote them.
+pub trait Lockfile: std::fmt::Debug {
+    /// Returns the format of the lock file.
+    fn lockfile_kind(&self) -> LockfileKind;
+
+    /// Returns every installed package, nested copies and workspaces included, sorted.
+    fn packages(&self) -> Vec<SimpleDependency>;
+
+    /// Returns the installed packages the root project depends on directly, sorted.
+    ///
+    /// npm v1 files don't record the root project, so this is approximate for them:
+    /// every top level package no other package requires is taken, missing a direct
+    /// dependency another package also requires.
+    fn root_dependencies(&self) -> Vec<SimpleDependency>;
+
+    /// Returns the workspaces of the project, sorted by path.
+    fn workspaces(&self) -> Vec<Workspace>;
+}
+
+impl Lockfile for PackageLockJson {
+    fn lockfile_kind(&self) -> LockfileKind {
+        LockfileKind::Npm {
+            lockfile_version: self.lockfile_version,
+        }
+    }
+
+    fn packages(&self) -> Vec<SimpleDependency> {
+        let mut entries = self
+            .installed_dependencies()
+            .into_iter()
+            .map(SimpleDependency::from)
+            .collect::<Vec<_>>();
+        entries.sort();
+        entries
+    }
+
+    fn root_dependencies(&self) -> Vec<SimpleDependency> {
+        let names = if let Some(packages) = &self.packages_by_path {
+            let Some(root) = packages.get("") else {
+                return Vec::new();
+            };
+            [
+                &root.dependencies,
+                &root.dev_dependencies,
+                &root.optional_dependencies,
+                &root.peer_dependencies,
+            ]
+            .into_iter()
+            .flatten()
+            .flat_map(|deps| deps.keys())
+            .map(String::as_str)
+            .collect::<HashSet<_>>()
+        } else if let Some(dependencies) = &self.dependencies {
+            v1_root_names(dependencies).collect()
+        } else {
+            HashSet::new()
+        };
+        let mut entries = names
+            .into_iter()
+            .filter_map(|name| root_dependency(self, name))
+            .collect::<Vec<_>>();
+        entries.sort();
+        entries
+    }
+
+    fn workspaces(&self) -> Vec<Workspace> {
+        let mut workspaces = Vec::new();
+        let Some(packages) = &self.packages_by_path else {
+            return workspaces;
+        };
+        for (path, name) in workspace_names(packages) {
+            let package = &packages[path];
+            workspaces.push(Workspace {
+                name,
+                path: path.to_string(),
+                version: (!package.version.is_empty()).then(|| package.version.clone()),
+            });
+        }
+        workspaces.sort_by(|a, b| a.path.cmp(&b.path));
+        workspaces
+    }
+}
+
+/// Returns the package installed for the dependency `name` of the root project,
+/// the workspace folder for links.
+fn root_dependency(lock_file: &PackageLockJson, name: &str) -> Option<SimpleDependency> {
+    let (version, is_dev, is_optional) = match &lock_file.packages_by_path {
+        Some(packages) => {
+            let mut package = packages.get(&format!("node_modules/{name}"))?;
+            if package.link {
+                // use the workspace folder the link points to.
+                package = packages.get(package.resolved.as_ref()?)?;
+            }
+            (
+                package.version.as_str(),
+                package.is_dev,
+                package.is_optional,
+            )
+        }
+        None => {
+            let dependency = lock_file.dependencies.as_ref()?.get(name)?;
+            // aliases are written npm:real-name@1.0.0 in v1 files.
+            let version = dependency
+                .version
+                .strip_prefix("npm:")
+                .and_then(|alias| alias.rsplit_once('@'))
+                .map_or(dependency.version.as_str(), |(_, version)| version);
+            (version, dependency.is_dev, dependency.is_optional)
+        }
+    };
+    Some(SimpleDependency {
+        name: PackageName::new(name).ok()?,
+        version: version.to_string(),
+        is_dev,
+        is_optional,
+    })
+}
+
+/// Returns the names of the top level v1 dependencies no other package requires.
+/// v1 files don't record the root project, so they are taken as its dependencies.
+pub(crate) fn v1_root_names(
+    dependencies: &Map<String, V1Dependency>,
+) -> impl Iterator<Item = &str> {
+    let mut required = HashSet::new();
+    collect_v1_requires(dependencies, &mut required);
+    dependencies
+        .keys()
+        .map(String::as_str)
+        .filter(move |name| !required.contains(name))
+}
+
+fn collect_v1_requires<'a>(
+    dependencies: &'a Map<String, V1Dependency>,
+    required: &mut HashSet<&'a str>,
+) {
+    for dependency in dependencies.values() {
+        if let Some(requires) = &dependency.requires {
+            required.extend(requires.keys().map(String::as_str));
+        }
+        if let Some(nested) = &dependency.dependencies {
+            collect_v1_requires(nested, required);
+        }
+    }
+}
+
+/// Lock file formats we can recognize.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Format {
+    Npm,
+    Unsupported(&'static str),
+}
+
+fn format_from_name(path: &Path) -> Option<Format> {
+    match path.file_name().and_then(|name| name.to_str())? {
+        "package-lock.json" | "npm-shrinkwrap.json" | ".package-lock.json" => Some(Format::Npm),
+        "yarn.lock" => Some(Format::Unsupported("yarn")),
+        "pnpm-lock.yaml" => Some(Format::Unsupported("pnpm")),
+        "bun.lock" | "bun.lockb" => Some(Format::Unsupported("bun")),
+        _ => None,
+    }
+}
+
+fn format_from_content(content: &str) -> Format {
+    let content = content.trim_start();
+    if content.starts_with('{') && content.contains("\"lockfileVersion\"") {
+        Format::Npm
+    } else if content.contains("# yarn lockfile v1") || content.contains("__metadata:") {
+        Format::Unsupported("yarn")
+    } else if content.starts_with("lockfileVersion:") {
+        Format::Unsupported("pnpm")
+    } else {
+        Format::Unsupported("unknown")
+    }
+}
+
+/// Reads a lock file and parses it with the parser matching its
+/// file name or, if the name is not a known one, its content.
+#[instrument(skip_all, fields(path = %path.as_ref().display()))]
+pub fn detect_and_parse(path: impl AsRef<Path>) -> Result<Box<dyn Lockfile>, PackageLockJsonError> {
+    let path = path.as_ref();
+    let format = match format_from_name(path) {
+        Some(format) => format,
+        None => {
+            // unknown name, let's look at the content.
+            let content =
+                fs::read_to_string(path).map_err(|source| PackageLockJsonError::IoError {
+                    path: path.to_path_buf(),
+                    source,
+                })?;
+            let format = format_from_content(&content);
+            if format == Format::Npm {
+                return Ok(Box::new(parse(content)?));
+            }
+            format
+        }
+    };
+    match format {
+        Format::Npm => Ok(Box::new(parse_file(path)?)),
+        Format::Unsupported(format) => Err(PackageLockJsonError::UnsupportedLockfileError(
+            format!("{} ({format})", path.display()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn detect_and_parse_works() {
+        let lock_file = detect_and_parse("tests/v3/package-lock.json").unwrap();
+        assert_eq!(
+            lock_file.lockfile_kind(),
+            LockfileKind::Npm {
+                lockfile_version: 3
+            }
+        );
+        // missing files keep the path in the error.
+        let err = detect_and_parse("tests/missing/package-lock.json").unwrap_err();
+        assert!(err.to_string().contains("tests/missing/package-lock.json"));
+    }
+
+    #[test]
+    fn detect_format_works() {
+        assert_eq!(
+            format_from_name(Path::new("a/yarn.lock")),
+            Some(Format::Unsupported("yarn"))
+        );
+        assert_eq!(format_from_name(Path::new("lock.json")), None);
+        assert_eq!(
+            format_from_content("{\n  \"lockfileVersion\": 3\n}"),
+            Format::Npm
+        );
+        assert_eq!(
+            format_from_content("lockfileVersion: '6.0'"),
+            Format::Unsupported("pnpm")
+        );
+    }
+
+    #[test]
+    fn lockfile_v1_works() {
+        let lock_file = detect_and_parse("tests/v1/package-lock.json").unwrap();
+        // js-tokens is installed twice.
+        let packages = lock_file.packages();
+        assert!(packages.iter().filter(|p| p.name == "js-tokens").count() > 1);
+
+        let roots = lock_file.root_dependencies();
+        assert!(roots.iter().any(|p| p.name == "rxjs"));
+        assert!(!roots.iter().any(|p| p.name == "@babel/highlight"));
+        assert!(lock_file.workspaces().is_empty());
+    }
+
+    #[test]
+    fn lockfile_v1_root_dependencies_are_approximate() {
+        // react is required by react-dom, the heuristic misses it.
+        let content = r#"{
+            "name": "app",
+            "lockfileVersion": 1,
+            "dependencies": {
+                "react": { "version": "18.2.0" },
+                "react-dom": { "version": "18.2.0", "requires": { "react": "^18.2.0" } }
+            }
+        }"#;
+        let names = |lock_file: &PackageLockJson| {
+            lock_file
+                .root_dependencies()
+                .into_iter()
+                .map(|dependency| dependency.name)
+                .collect::<Vec<_>>()
+        };
+        let lock_file = parse(content).unwrap();
+        assert_eq!(names(&lock_file), ["react-dom"]);
+    }
+
+    #[test]
+    fn lockfile_workspaces_work() {
+        let lock_file = detect_and_parse("tests/workspace/v3/package-lock.json").unwrap();
+        assert_eq!(
+            lock_file.workspaces(),
+            vec![
+                Workspace {
+                    name: "liba".parse().unwrap(),
+                    path: "liba".to_string(),
+                    version: Some("1.0.0".to_string()),
+                },
+                Workspace {
+                    name: "libb2".parse().unwrap(),
+                    path: "libb".to_string(),
+                    version: Some("1.0.0".to_string()),
+                },
+            ]
+        );
+
+        let roots = lock_file.root_dependencies();
+        let chai = roots.iter().find(|p| p.name == "chai").unwrap();
+        assert!(chai.is_dev);
+        // the workspace is a package, the link pointing to it is not.
+        assert_eq!(
+            lock_file
+                .packages()
+                .iter()
+                .filter(|p| p.name == "liba")
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use std::path::Path;
+
+use rayon::prelude::*;
+use tracing::instrument;
+
+use crate::{parse, parse_file, PackageLockJson, PackageLockJsonError};
+
+/// Parses many package-lock.json files in parallel.
+/// Results are returned in the same order as the contents.
+#[instrument(skip_all, fields(count = contents.len()))]
+pub fn parse_many<S>(contents: &[S]) -> Vec<Result<PackageLockJson, PackageLockJsonError>>
+where
+    S: AsRef<str> + Sync,
+{
+    contents
+        .par_iter()
+        .map(|content| parse(content.as_ref()))
+        .collect()
+}
+
+/// Reads and parses many package-lock.json files in parallel.
+/// Results are returned in the same order as the paths.
+#[instrument(skip_all, fields(count = paths.len()))]
+pub fn parse_many_files<P>(paths: &[P]) -> Vec<Result<PackageLockJson, PackageLockJsonError>>
+where
+    P: AsRef<Path> + Sync,
+{
+    paths.par_iter().map(parse_file).collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parse_many_files_works() {
+        let paths = [
+            "tests/v1/package-lock.json",
+            "tests/missing/package-lock.json",
+            "tests/workspace/v3/package-lock.json",
+        ];
+        let results = parse_many_files(&paths);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().lockfile_version, 1);
+        assert!(matches!(
+            results[1],
+            Err(PackageLockJsonError::IoError { .. })
+        ));
+        assert_eq!(results[2].as_ref().unwrap().name, "kk");
+    }
+
+    #[test]
+    fn parse_many_works() {
+        let content = std::fs::read_to_string("tests/v3/package-lock.json").unwrap();
+        let results = parse_many(&[content.as_str(), "{}"]);
+        assert_eq!(
+            results[0].as_ref().unwrap(),
+            &parse(content.as_str()).unwrap()
+        );
+        assert!(results[1].is_err());
+    }
+}
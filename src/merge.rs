@@ -0,0 +1,618 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graph::parent_folder, name::is_project_path, parse, Map, PackageLockJson, PackageLockJsonError,
+    V2Dependency,
+};
+
+/// Lock file resulting from a merge and what couldn't be merged in it.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MergedLockFile {
+    /// Merged lock file. Conflicts keep our side.
+    pub lock_file: PackageLockJson,
+    /// Conflicts sorted by path.
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// Entry both sides changed in a different way.
+#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct MergeConflict {
+    /// Install path of the package, `""` for the root project.
+    pub path: String,
+    /// Dependency of the root project or a workspace both sides changed,
+    /// `None` if the conflict is on the installed package.
+    pub dependency: Option<String>,
+    /// Version, or range of the dependency, in every side. `None` if it isn't there.
+    pub base: Option<String>,
+    pub ours: Option<String>,
+    pub theirs: Option<String>,
+}
+
+/// Merges the changes `ours` and `theirs` made to `base`, package by package.
+///
+/// A package changed in a single side takes that change. Both sides installing
+/// something different in the same path is a conflict, and our side is kept:
+/// a different version, or the same one from another url, checksum or with other dependencies.
+/// So is a side removing a package the other side changed the nested packages of.
+/// The dependencies of the root project and the workspaces are merged one by one,
+/// so adding different dependencies in each side is not a conflict.
+/// The merged file has our lockfileVersion.
+pub fn merge(
+    base: &PackageLockJson,
+    ours: &PackageLockJson,
+    theirs: &PackageLockJson,
+) -> MergedLockFile {
+    let (base_packages, our_packages, their_packages) =
+        (base.v2_packages(), ours.v2_packages(), theirs.v2_packages());
+    let paths = base_packages
+        .keys()
+        .chain(our_packages.keys())
+        .chain(their_packages.keys())
+        .collect::<BTreeSet<_>>();
+
+    let mut conflicts = Vec::new();
+    let mut packages = Map::new();
+    for path in paths {
+        let base = base_packages.get(path);
+        let merged = match (our_packages.get(path), their_packages.get(path)) {
+            (Some(ours), Some(theirs)) if is_project_path(path) => {
+                Some(merge_project(path, base, ours, theirs, &mut conflicts))
+            }
+            (ours, theirs) => three_way(base, ours, theirs)
+                .unwrap_or_else(|| {
+                    if !same_install(ours, theirs) {
+                        conflicts.push(MergeConflict {
+                            path: path.clone(),
+                            dependency: None,
+                            base: version(base).cloned(),
+                            ours: version(ours).cloned(),
+                            theirs: version(theirs).cloned(),
+                        });
+                    }
+                    ours
+                })
+                .cloned(),
+        };
+        if let Some(package) = merged {
+            packages.insert(path.clone(), package);
+        }
+    }
+    let sides = [&base_packages, &our_packages, &their_packages];
+    merge_orphans(sides, &mut packages, &mut conflicts);
+    conflicts.sort();
+
+    let lock_file = PackageLockJson {
+        name: three_way(Some(&base.name), Some(&ours.name), Some(&theirs.name))
+            .flatten()
+            .unwrap_or(&ours.name)
+            .clone(),
+        version: three_way(
+            base.version.as_ref(),
+            ours.version.as_ref(),
+            theirs.version.as_ref(),
+        )
+        .unwrap_or(ours.version.as_ref())
+        .cloned(),
+        lockfile_version: ours.lockfile_version,
+        packages: None,
+        packages_by_path: Some(packages),
+        dependencies: None,
+        extra: ours.extra.clone(),
+    };
+    let lock_file = match lock_file.lockfile_version {
+        1 => lock_file.to_v1(),
+        2 => lock_file.to_v2(),
+        _ => lock_file.to_v3(),
+    };
+    MergedLockFile {
+        lock_file,
+        conflicts,
+    }
+}
+
+/// Merges the sides of a lock file with git conflict markers in it.
+///
+/// The base is read from `diff3` style conflicts if every conflict has it.
+/// Otherwise what both sides have in common is taken as the base,
+/// so what only one side has is kept.
+pub fn merge_conflicted(content: &str) -> Result<MergedLockFile, PackageLockJsonError> {
+    let sides = split_conflict(content)?;
+    let ours = parse(sides.ours)?;
+    let theirs = parse(sides.theirs)?;
+    let base = match sides.base {
+        Some(base) => parse(base)?,
+        None => common_base(&ours, &theirs),
+    };
+    Ok(merge(&base, &ours, &theirs))
+}
+
+/// Handles the packages nested in a package one side removed and the other changed
+/// packages nested in: it's a conflict on the removed package, and our side is kept.
+/// The removed package comes back with what we have nested in it if we have it,
+/// the packages nested in it go otherwise.
+fn merge_orphans(
+    [base, ours, theirs]: [&Map<String, V2Dependency>; 3],
+    packages: &mut Map<String, V2Dependency>,
+    conflicts: &mut Vec<MergeConflict>,
+) {
+    loop {
+        let orphan = packages
+            .keys()
+            .map(|path| parent_folder(path))
+            .filter(|parent| !parent.is_empty() && !packages.contains_key(*parent))
+            .min()
+            .map(ToString::to_string);
+        let Some(parent) = orphan else {
+            return;
+        };
+        let nested = format!("{parent}/node_modules/");
+        match ours.get(&parent) {
+            Some(package) => {
+                packages.insert(parent.clone(), package.clone());
+                for (path, package) in ours {
+                    if path.starts_with(&nested) && !packages.contains_key(path) {
+                        packages.insert(path.clone(), package.clone());
+                    }
+                }
+            }
+            None => packages.retain(|path, _| !path.starts_with(&nested)),
+        }
+        conflicts.push(MergeConflict {
+            dependency: None,
+            base: version(base.get(&parent)).cloned(),
+            ours: version(ours.get(&parent)).cloned(),
+            theirs: version(theirs.get(&parent)).cloned(),
+            path: parent,
+        });
+    }
+}
+
+/// Returns the side that changed, `None` if both changed in a different way.
+fn three_way<T: PartialEq>(
+    base: Option<T>,
+    ours: Option<T>,
+    theirs: Option<T>,
+) -> Option<Option<T>> {
+    if ours == theirs || theirs == base {
+        Some(ours)
+    } else if ours == base {
+        Some(theirs)
+    } else {
+        None
+    }
+}
+
+fn version(package: Option<&V2Dependency>) -> Option<&String> {
+    package.map(|package| &package.version)
+}
+
+/// Returns whether both sides installed the same thing, flags like `dev` aside
+/// as they follow from the rest of the tree.
+fn same_install(ours: Option<&V2Dependency>, theirs: Option<&V2Dependency>) -> bool {
+    match (ours, theirs) {
+        (Some(ours), Some(theirs)) => {
+            ours.version == theirs.version
+                && ours.resolved == theirs.resolved
+                && ours.integrity == theirs.integrity
+                && ours.dependencies == theirs.dependencies
+        }
+        _ => false,
+    }
+}
+
+/// Merges the entry of the root project or a workspace, dependency by dependency.
+fn merge_project(
+    path: &str,
+    base: Option<&V2Dependency>,
+    ours: &V2Dependency,
+    theirs: &V2Dependency,
+    conflicts: &mut Vec<MergeConflict>,
+) -> V2Dependency {
+    let mut merge_dependencies = |select: fn(&V2Dependency) -> &Option<Map<String, String>>| {
+        let (base, ours, theirs) = (
+            base.and_then(|p| select(p).as_ref()),
+            select(ours),
+            select(theirs),
+        );
+        let names = base
+            .into_iter()
+            .chain(ours)
+            .chain(theirs)
+            .flat_map(Map::keys)
+            .collect::<BTreeSet<_>>();
+        let mut merged = Map::new();
+        for name in names {
+            let (base, ours, theirs) = (
+                base.and_then(|dependencies| dependencies.get(name)),
+                ours.as_ref()
+                    .and_then(|dependencies| dependencies.get(name)),
+                theirs
+                    .as_ref()
+                    .and_then(|dependencies| dependencies.get(name)),
+            );
+            let range = three_way(base, ours, theirs).unwrap_or_else(|| {
+                conflicts.push(MergeConflict {
+                    path: path.to_string(),
+                    dependency: Some(name.clone()),
+                    base: base.cloned(),
+                    ours: ours.cloned(),
+                    theirs: theirs.cloned(),
+                });
+                ours
+            });
+            if let Some(range) = range {
+                merged.insert(name.clone(), range.clone());
+            }
+        }
+        (!merged.is_empty()).then_some(merged)
+    };
+    let dependencies = merge_dependencies(|p| &p.dependencies);
+    let dev_dependencies = merge_dependencies(|p| &p.dev_dependencies);
+    let optional_dependencies = merge_dependencies(|p| &p.optional_dependencies);
+    let peer_dependencies = merge_dependencies(|p| &p.peer_dependencies);
+
+    // the rest of the fields as a whole.
+    let package = if Some(ours) == base { theirs } else { ours };
+    V2Dependency {
+        dependencies,
+        dev_dependencies,
+        optional_dependencies,
+        peer_dependencies,
+        ..package.clone()
+    }
+}
+
+/// What both sides have in common, used as base when there is none.
+fn common_base(ours: &PackageLockJson, theirs: &PackageLockJson) -> PackageLockJson {
+    let their_packages = theirs.v2_packages();
+    let packages = ours
+        .v2_packages()
+        .into_iter()
+        .filter(|(path, package)| their_packages.get(path) == Some(package))
+        .collect();
+    PackageLockJson {
+        packages: None,
+        packages_by_path: Some(packages),
+        dependencies: None,
+        ..ours.clone()
+    }
+}
+
+/// Contents of every side of a file with conflict markers.
+struct ConflictSides {
+    base: Option<String>,
+    ours: String,
+    theirs: String,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Section {
+    Common,
+    Ours,
+    Base,
+    Theirs,
+}
+
+fn split_conflict(content: &str) -> Result<ConflictSides, PackageLockJsonError> {
+    let mut ours = String::new();
+    let mut theirs = String::new();
+    let mut base = Some(String::new());
+    let mut section = Section::Common;
+    let mut has_base = false;
+    for (number, line) in content.split_inclusive('\n').enumerate() {
+        let marker = ["<<<<<<<", "|||||||", "=======", ">>>>>>>"]
+            .into_iter()
+            .find(|marker| line.starts_with(marker));
+        section = match (marker, section) {
+            (None, _) => {
+                match section {
+                    Section::Common => {
+                        ours.push_str(line);
+                        theirs.push_str(line);
+                        if let Some(base) = &mut base {
+                            base.push_str(line);
+                        }
+                    }
+                    Section::Ours => ours.push_str(line),
+                    Section::Base => {
+                        if let Some(base) = &mut base {
+                            base.push_str(line);
+                        }
+                    }
+                    Section::Theirs => theirs.push_str(line),
+                }
+                continue;
+            }
+            (Some("<<<<<<<"), Section::Common) => {
+                has_base = false;
+                Section::Ours
+            }
+            (Some("|||||||"), Section::Ours) => {
+                has_base = true;
+                Section::Base
+            }
+            (Some("======="), Section::Ours | Section::Base) => {
+                if !has_base {
+                    // plain conflicts don't tell what the base was.
+                    base = None;
+                }
+                Section::Theirs
+            }
+            (Some(">>>>>>>"), Section::Theirs) => Section::Common,
+            (Some(marker), _) => {
+                return Err(PackageLockJsonError::ConflictMarkersError(format!(
+                    "unexpected {marker} at line {}",
+                    number + 1
+                )))
+            }
+        };
+    }
+    if section != Section::Common {
+        return Err(PackageLockJsonError::ConflictMarkersError(
+            "unterminated conflict".to_string(),
+        ));
+    }
+    if ours == theirs {
+        return Err(PackageLockJsonError::ConflictMarkersError(
+            "no conflicts found".to_string(),
+        ));
+    }
+    Ok(ConflictSides { base, ours, theirs })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn v2_lock_file() -> PackageLockJson {
+        let content = std::fs::read_to_string("tests/v2/package-lock.json").unwrap();
+        parse(content).unwrap()
+    }
+
+    fn add_dependency(lock_file: &mut PackageLockJson, name: &str, range: &str) {
+        let root = lock_file
+            .packages_by_path
+            .as_mut()
+            .unwrap()
+            .get_mut("")
+            .unwrap();
+        root.dependencies
+            .get_or_insert_with(Map::new)
+            .insert(name.to_string(), range.to_string());
+    }
+
+    #[test]
+    fn merge_works() {
+        let base = v2_lock_file();
+        let mut ours = base.clone();
+        ours.set_package_version("node_modules/rxjs", "7.8.1", None, None)
+            .unwrap();
+        ours.set_package_version("node_modules/tslib", "2.5.0", None, None)
+            .unwrap();
+        add_dependency(&mut ours, "left-pad", "^1.3.0");
+        ours.insert_package(
+            "node_modules/left-pad",
+            V2Dependency {
+                version: "1.3.0".to_string(),
+                ..V2Dependency::default()
+            },
+        )
+        .unwrap();
+
+        let mut theirs = base.clone();
+        theirs
+            .set_package_version("node_modules/tslib", "2.6.0", None, None)
+            .unwrap();
+        add_dependency(&mut theirs, "right-pad", "^1.0.0");
+        theirs
+            .insert_package(
+                "node_modules/right-pad",
+                V2Dependency {
+                    version: "1.0.1".to_string(),
+                    ..V2Dependency::default()
+                },
+            )
+            .unwrap();
+        theirs
+            .remove_package("node_modules/@babel/highlight")
+            .unwrap();
+
+        let merged = merge(&base, &ours, &theirs);
+        assert_eq!(
+            merged.conflicts,
+            [MergeConflict {
+                path: "node_modules/tslib".to_string(),
+                dependency: None,
+                base: Some("2.4.1".to_string()),
+                ours: Some("2.5.0".to_string()),
+                theirs: Some("2.6.0".to_string()),
+            }]
+        );
+
+        let lock_file = &merged.lock_file;
+        assert_eq!(
+            lock_file.package("node_modules/rxjs").unwrap().version,
+            "7.8.1"
+        );
+        assert_eq!(
+            lock_file.package("node_modules/tslib").unwrap().version,
+            "2.5.0"
+        );
+        assert!(lock_file.package("node_modules/left-pad").is_some());
+        assert!(lock_file.package("node_modules/right-pad").is_some());
+        assert!(lock_file.package("node_modules/@babel/highlight").is_none());
+        let root = lock_file
+            .package("")
+            .unwrap()
+            .dependencies
+            .as_ref()
+            .unwrap();
+        assert!(root.contains_key("left-pad") && root.contains_key("right-pad"));
+
+        // both sections are there and in sync.
+        assert_eq!(lock_file.lockfile_version, 2);
+        assert_eq!(
+            lock_file.dependencies.as_ref().unwrap()["right-pad"].version,
+            "1.0.1"
+        );
+        let json = lock_file.to_json().unwrap();
+        assert_eq!(parse(json).unwrap(), *lock_file);
+    }
+
+    #[test]
+    fn merge_finds_same_version_conflicts() {
+        let base = v2_lock_file();
+        let mut ours = base.clone();
+        let resolved = "https://registry.npmjs.org/tslib/-/tslib-2.5.0.tgz".to_string();
+        ours.set_package_version(
+            "node_modules/tslib",
+            "2.5.0",
+            Some(resolved.clone()),
+            Some("sha512-ours".to_string()),
+        )
+        .unwrap();
+        let mut theirs = base.clone();
+        theirs
+            .set_package_version(
+                "node_modules/tslib",
+                "2.5.0",
+                Some(resolved.clone()),
+                Some("sha512-theirs".to_string()),
+            )
+            .unwrap();
+
+        let merged = merge(&base, &ours, &theirs);
+        assert_eq!(
+            merged.conflicts,
+            [MergeConflict {
+                path: "node_modules/tslib".to_string(),
+                dependency: None,
+                base: Some("2.4.1".to_string()),
+                ours: Some("2.5.0".to_string()),
+                theirs: Some("2.5.0".to_string()),
+            }]
+        );
+        let tslib = merged.lock_file.package("node_modules/tslib").unwrap();
+        assert_eq!(tslib.integrity.as_deref(), Some("sha512-ours"));
+
+        // the same change in both sides merges.
+        let merged = merge(&base, &ours, &ours);
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn merge_finds_orphaned_packages() {
+        let highlight = "node_modules/@babel/highlight";
+        let nested = "node_modules/@babel/highlight/node_modules/chalk";
+        let base = v2_lock_file();
+        let mut removed = base.clone();
+        removed.remove_package(highlight).unwrap();
+        let mut added = base.clone();
+        added
+            .insert_package(
+                nested,
+                V2Dependency {
+                    version: "2.4.2".to_string(),
+                    ..V2Dependency::default()
+                },
+            )
+            .unwrap();
+        let conflict = |ours: Option<&str>, theirs: Option<&str>| MergeConflict {
+            path: highlight.to_string(),
+            dependency: None,
+            base: Some("7.18.6".to_string()),
+            ours: ours.map(ToString::to_string),
+            theirs: theirs.map(ToString::to_string),
+        };
+
+        // we removed the package, the packages nested in it go.
+        let merged = merge(&base, &removed, &added);
+        assert_eq!(merged.conflicts, [conflict(None, Some("7.18.6"))]);
+        let packages = merged.lock_file.packages_by_path.as_ref().unwrap();
+        assert!(!packages
+            .keys()
+            .any(|path| path.starts_with("node_modules/@babel/highlight")));
+
+        // they removed it, we keep it with the packages nested in it.
+        let merged = merge(&base, &added, &removed);
+        assert_eq!(merged.conflicts, [conflict(Some("7.18.6"), None)]);
+        let lock_file = &merged.lock_file;
+        assert_eq!(lock_file.package(highlight), base.package(highlight));
+        assert_eq!(lock_file.package(nested).unwrap().version, "2.4.2");
+        assert!(lock_file
+            .package("node_modules/@babel/highlight/node_modules/js-tokens")
+            .is_some());
+    }
+
+    #[test]
+    fn merge_conflicted_works() {
+        let content = r#"{
+  "name": "app",
+  "lockfileVersion": 3,
+  "packages": {
+    "": {
+      "dependencies": {
+<<<<<<< HEAD
+        "a": "^1.1.0",
+        "b": "^1.0.0"
+=======
+        "a": "^1.0.0",
+        "c": "^1.0.0"
+>>>>>>> feature
+      }
+    },
+    "node_modules/a": {
+<<<<<<< HEAD
+      "version": "1.1.0"
+    },
+    "node_modules/b": {
+      "version": "1.0.0"
+=======
+      "version": "1.2.0"
+    },
+    "node_modules/c": {
+      "version": "1.0.0"
+>>>>>>> feature
+    }
+  }
+}
+"#;
+        let merged = merge_conflicted(content).unwrap();
+        let conflicts = merged
+            .conflicts
+            .iter()
+            .map(|conflict| (conflict.path.as_str(), conflict.dependency.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(conflicts, [("", Some("a")), ("node_modules/a", None)]);
+        let lock_file = &merged.lock_file;
+        assert_eq!(
+            lock_file.package("node_modules/a").unwrap().version,
+            "1.1.0"
+        );
+        assert!(lock_file.package("node_modules/b").is_some());
+        assert!(lock_file.package("node_modules/c").is_some());
+
+        // diff3 conflicts have the base.
+        let content = content
+            .replace(
+                "        \"b\": \"^1.0.0\"\n=======",
+                "        \"b\": \"^1.0.0\"\n||||||| base\n        \"a\": \"^1.0.0\"\n=======",
+            )
+            .replace(
+                "      \"version\": \"1.0.0\"\n=======",
+                "      \"version\": \"1.0.0\"\n||||||| base\n      \"version\": \"1.0.0\"\n=======",
+            );
+        let merged = merge_conflicted(&content).unwrap();
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(merged.conflicts[0].base.as_deref(), Some("1.0.0"));
+
+        let err = merge_conflicted("{\n<<<<<<< HEAD\n}\n");
+        assert!(matches!(
+            err,
+            Err(PackageLockJsonError::ConflictMarkersError(_))
+        ));
+    }
+}
@@ -0,0 +1,262 @@
+use std::{borrow::Borrow, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::PackageLockJsonError;
+
+/// An npm package name, optionally scoped, e.g. `react` or `@babel/highlight`.
+///
+/// Names are checked against the rules npm applies to every package.
+/// The stricter rules for new packages, like being lowercase, aren't,
+/// as lock files still install packages published before them.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PackageName(String);
+
+impl PackageName {
+    pub fn new(name: impl Into<String>) -> Result<Self, PackageLockJsonError> {
+        let name = name.into();
+        let invalid =
+            |reason: &str| PackageLockJsonError::PackageNameError(format!("{name:?} {reason}"));
+        if name.is_empty() {
+            return Err(invalid("is empty"));
+        }
+        if name.starts_with('.') || name.starts_with('_') {
+            return Err(invalid("can't start with a period or an underscore"));
+        }
+        if name.trim() != name {
+            return Err(invalid("can't have leading or trailing spaces"));
+        }
+        if ["node_modules", "favicon.ico"].contains(&name.to_lowercase().as_str()) {
+            return Err(invalid("is not allowed"));
+        }
+        let parts = match name.strip_prefix('@') {
+            Some(scoped) => match scoped.split_once('/') {
+                Some((scope, bare)) if !scope.is_empty() && !bare.is_empty() => vec![scope, bare],
+                _ => return Err(invalid("has an invalid scope")),
+            },
+            None => vec![name.as_str()],
+        };
+        if !parts.iter().all(|part| part.chars().all(is_url_safe)) {
+            return Err(invalid("can only contain url friendly characters"));
+        }
+        Ok(Self(name))
+    }
+
+    /// Returns the scope with its `@`, e.g. `@babel` in `@babel/highlight`.
+    pub fn scope(&self) -> Option<&str> {
+        self.0
+            .starts_with('@')
+            .then(|| self.0.split_once('/').map(|(scope, _)| scope))
+            .flatten()
+    }
+
+    /// Returns the name without the scope, e.g. `highlight` in `@babel/highlight`.
+    pub fn name(&self) -> &str {
+        match self.scope() {
+            Some(scope) => &self.0[scope.len() + 1..],
+            None => &self.0,
+        }
+    }
+
+    pub fn is_scoped(&self) -> bool {
+        self.scope().is_some()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Parses the names of the packages along an install path,
+    /// e.g. `node_modules/a/node_modules/@s/b` gives `a` and `@s/b`.
+    ///
+    /// The folder a path starts in, like the one of a workspace, is not a name and is skipped,
+    /// so the root project and the workspaces give no names.
+    pub fn from_install_path(path: &str) -> Result<Vec<Self>, PackageLockJsonError> {
+        let (_, names) = split_install_path(path).ok_or_else(|| {
+            PackageLockJsonError::PackageNameError(format!("Invalid path {path:?}"))
+        })?;
+        names.into_iter().map(Self::new).collect()
+    }
+}
+
+/// Splits an install path into the folder it starts in, `""` for the root project,
+/// and the names along it, without validating them.
+/// `None` if the path is malformed, e.g. `node_modules/@scope`.
+pub(crate) fn split_install_path(path: &str) -> Option<(&str, Vec<&str>)> {
+    let (folder, mut rest) = if let Some(rest) = path.strip_prefix("node_modules/") {
+        ("", rest)
+    } else if let Some((folder, rest)) = path.split_once("/node_modules/") {
+        (folder, rest)
+    } else {
+        return Some((path, Vec::new()));
+    };
+    let mut names = Vec::new();
+    loop {
+        // scoped names take two segments.
+        let segments = if rest.starts_with('@') { 2 } else { 1 };
+        let end = rest
+            .match_indices('/')
+            .nth(segments - 1)
+            .map_or(rest.len(), |(i, _)| i);
+        let name = &rest[..end];
+        if name.is_empty() || name.ends_with('/') || name.matches('/').count() != segments - 1 {
+            return None;
+        }
+        names.push(name);
+        if end == rest.len() {
+            return Some((folder, names));
+        }
+        rest = rest[end + 1..].strip_prefix("node_modules/")?;
+    }
+}
+
+/// Returns whether a path is a folder of the project, the root `""` or a workspace,
+/// rather than a package installed in `node_modules`.
+pub(crate) fn is_project_path(path: &str) -> bool {
+    !path.starts_with("node_modules/") && !path.contains("/node_modules/")
+}
+
+/// Returns the name of the folder a package is installed in, `None` for project folders.
+pub(crate) fn folder_name(path: &str) -> Option<&str> {
+    split_install_path(path)?.1.pop()
+}
+
+/// Characters `encodeURIComponent` keeps as they are.
+fn is_url_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-_.!~*'()".contains(c)
+}
+
+impl FromStr for PackageName {
+    type Err = PackageLockJsonError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::new(value)
+    }
+}
+
+impl TryFrom<String> for PackageName {
+    type Error = PackageLockJsonError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl From<PackageName> for String {
+    fn from(name: PackageName) -> Self {
+        name.0
+    }
+}
+
+impl fmt::Display for PackageName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for PackageName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Lets maps keyed by name be queried with a `&str`.
+impl Borrow<str> for PackageName {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for PackageName {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for PackageName {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn package_name_works() {
+        let name = PackageName::new("@babel/highlight").unwrap();
+        assert_eq!(name.scope(), Some("@babel"));
+        assert_eq!(name.name(), "highlight");
+        assert!(name.is_scoped());
+        assert_eq!(name, "@babel/highlight");
+
+        let name = "JSONStream".parse::<PackageName>().unwrap();
+        assert_eq!((name.scope(), name.name()), (None, "JSONStream"));
+
+        for invalid in [
+            "",
+            ".bin",
+            "_private",
+            " a",
+            "node_modules",
+            "@babel",
+            "@/a",
+            "@babel/",
+            "a/b",
+            "@a/b/c",
+            "a b",
+            "ümlaut",
+        ] {
+            assert!(PackageName::new(invalid).is_err(), "{invalid}");
+        }
+
+        let names: Vec<PackageName> = serde_json::from_str(r#"["a", "@s/b"]"#).unwrap();
+        assert_eq!(serde_json::to_string(&names).unwrap(), r#"["a","@s/b"]"#);
+        assert!(serde_json::from_str::<PackageName>(r#""a/b""#).is_err());
+    }
+
+    #[test]
+    fn from_install_path_works() {
+        let names = |path: &str| {
+            PackageName::from_install_path(path)
+                .unwrap()
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("node_modules/a"), ["a"]);
+        assert_eq!(
+            names("node_modules/a/node_modules/@s/b/node_modules/c"),
+            ["a", "@s/b", "c"]
+        );
+        // a package named like the folder other packages are installed in.
+        assert_eq!(
+            names("node_modules/@node_modules/a/node_modules/b"),
+            ["@node_modules/a", "b"]
+        );
+        assert_eq!(names("libs/telemetry/node_modules/uuid"), ["uuid"]);
+        assert!(names("").is_empty());
+        assert!(names("libs/telemetry").is_empty());
+
+        assert!(is_project_path(""));
+        assert!(is_project_path("packages/node_modules-utils"));
+        assert!(is_project_path("packages/my-node_modules/a"));
+        assert!(!is_project_path("packages/a/node_modules/b"));
+
+        for invalid in [
+            "node_modules/",
+            "node_modules/@s",
+            "node_modules/a/b",
+            "node_modules/a/node_modules/",
+            "node_modules/.bin",
+        ] {
+            assert!(
+                PackageName::from_install_path(invalid).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+}
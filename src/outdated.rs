@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graph,
+    name::{folder_name, is_project_path},
+    registry::PackumentSource,
+    semver::spec_range,
+    Map, PackageLockJson, PackageLockJsonError, PackageName, Version,
+};
+
+/// Installed package with a newer version in the registry, like `npm outdated` lists them.
+#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct OutdatedPackage {
+    pub name: PackageName,
+    /// Install path of the package.
+    pub path: String,
+    /// Install path of the package depending on it, `""` for the root project.
+    pub dependent: String,
+    /// Range the dependent declares.
+    pub range: String,
+    /// Installed version.
+    pub current: String,
+    /// Version the range would get today, `None` if none is published.
+    pub wanted: Option<String>,
+    /// Version tagged `latest`.
+    pub latest: Option<String>,
+}
+
+impl PackageLockJson {
+    /// Returns the installed packages behind the version their range wants
+    /// or the latest one, once per dependent, sorted by name and dependent.
+    ///
+    /// Like npm, the wanted version is the `latest` one if the range accepts it,
+    /// or else the highest version in range.
+    /// Packages missing from the registry, installed at a version that isn't semver,
+    /// e.g. from git, and non semver ranges are skipped with a warning.
+    /// Packuments are fetched once per package, and an error of the registry fails the whole call.
+    pub fn outdated(
+        &self,
+        registry: &impl PackumentSource,
+    ) -> Result<Vec<OutdatedPackage>, PackageLockJsonError> {
+        let packages = self.v2_packages();
+        let mut packuments = Map::new();
+        let mut outdated = Vec::new();
+        for dependent in packages.keys() {
+            for edge in graph::edges(&packages, dependent) {
+                let (Some(path), Some(range)) = (edge.path, spec_range(edge.spec)) else {
+                    continue;
+                };
+                if is_project_path(path) {
+                    // a workspace.
+                    continue;
+                }
+                let package = &packages[path];
+                let name = package
+                    .name
+                    .as_deref()
+                    .or_else(|| folder_name(path))
+                    .unwrap_or(path);
+                let Ok(current) = package.version.parse::<Version>() else {
+                    tracing::warn!(
+                        "{} is installed at {:?}, which isn't semver. Skipping it.",
+                        path,
+                        package.version
+                    );
+                    continue;
+                };
+                let Ok(package_name) = PackageName::new(name) else {
+                    tracing::warn!("{} is not a valid package name. Skipping it.", name);
+                    continue;
+                };
+                if !packuments.contains_key(name) {
+                    let packument = registry.packument(name)?;
+                    if packument.is_none() {
+                        tracing::warn!("No packument found for {}. Skipping it.", name);
+                    }
+                    packuments.insert(name.to_string(), packument);
+                }
+                let Some(packument) = &packuments[name] else {
+                    continue;
+                };
+
+                let latest = packument.dist_tags.get("latest");
+                let latest_version = latest.and_then(|latest| latest.parse::<Version>().ok());
+                let wanted = match &latest_version {
+                    Some(latest) if range.satisfies(latest) => Some(latest.clone()),
+                    _ => {
+                        let versions = packument.sorted_versions();
+                        range
+                            .max_satisfying(versions.iter().map(|(version, _)| version))
+                            .cloned()
+                    }
+                };
+                let behind = |version: &Option<Version>| {
+                    version.as_ref().is_some_and(|version| *version != current)
+                };
+                if !behind(&wanted) && !behind(&latest_version) {
+                    continue;
+                }
+                outdated.push(OutdatedPackage {
+                    name: package_name,
+                    path: path.to_string(),
+                    dependent: dependent.clone(),
+                    range: edge.spec.to_string(),
+                    current: package.version.clone(),
+                    wanted: wanted.map(|version| version.to_string()),
+                    latest: latest.cloned(),
+                });
+            }
+        }
+        outdated.sort_by(|a, b| (&a.name, &a.dependent).cmp(&(&b.name, &b.dependent)));
+        Ok(outdated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{parse, PackageName, PackumentDir};
+
+    #[test]
+    fn outdated_works() {
+        let content = std::fs::read_to_string("tests/upgrade/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap();
+        let registry = PackumentDir::new("tests/registry");
+        let outdated = lock_file
+            .outdated(&registry)
+            .unwrap()
+            .into_iter()
+            .map(|package| {
+                (
+                    package.name,
+                    package.dependent,
+                    package.current,
+                    package.wanted.unwrap(),
+                    package.latest.unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let row = |name: &str, dependent: &str, current: &str, wanted: &str, latest: &str| {
+            (
+                name.parse::<PackageName>().unwrap(),
+                dependent.to_string(),
+                current.to_string(),
+                wanted.to_string(),
+                latest.to_string(),
+            )
+        };
+        assert_eq!(
+            outdated,
+            [
+                row("@scope/c", "node_modules/a", "1.0.0", "1.0.0", "2.0.0"),
+                row("@scope/c", "node_modules/b", "1.0.0", "1.0.0", "2.0.0"),
+                row("a", "", "1.0.0", "1.2.0", "1.2.0"),
+                // latest is out of range.
+                row("b", "", "1.0.0", "1.5.0", "2.0.0"),
+            ]
+        );
+    }
+
+    #[test]
+    fn outdated_skips_non_semver_installs() {
+        let content = r#"{
+            "name": "app",
+            "lockfileVersion": 3,
+            "packages": {
+                "": { "dependencies": { "a": "^1.0.0", "b": "github:user/b" } },
+                "node_modules/a": { "version": "git+ssh://git@github.com/user/a.git#abc" },
+                "node_modules/b": { "version": "1.0.0" }
+            }
+        }"#;
+        let lock_file = parse(content).unwrap();
+        let registry = PackumentDir::new("tests/registry");
+        assert!(lock_file.outdated(&registry).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    graph, name::is_project_path, semver::spec_range, PackageJson, PackageLockJson,
+    PackageLockJsonError, PackageName,
+};
+
+/// The `overrides` of a package.json manifest.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Overrides {
+    pub rules: Vec<OverrideRule>,
+}
+
+/// Override of a package, and of the packages in its dependency tree.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct OverrideRule {
+    /// Key as written, e.g. `foo` or `foo@^1.0.0`.
+    pub key: String,
+    pub name: String,
+    /// Only installed versions in this range are overridden.
+    pub selector: Option<String>,
+    /// Spec the package is replaced with, `$` references already resolved.
+    pub value: Option<String>,
+    /// Overrides that only apply to the dependency tree of the package.
+    pub children: Vec<OverrideRule>,
+}
+
+/// Installed package that doesn't match the override that applies to it.
+#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct OverrideViolation {
+    pub name: PackageName,
+    pub version: String,
+    /// Install path of the package.
+    pub path: String,
+    /// Keys leading to the override, e.g. `foo > bar`.
+    pub rule: String,
+    /// Spec the override requires.
+    pub expected: String,
+}
+
+impl PackageJson {
+    /// Parses the `overrides` of the manifest.
+    /// `$name` values are replaced by the spec of the `name` dependency of the manifest.
+    pub fn parse_overrides(&self) -> Result<Overrides, PackageLockJsonError> {
+        let rules = match &self.overrides {
+            Some(overrides) => parse_rules(overrides.iter(), self)?,
+            None => Vec::new(),
+        };
+        Ok(Overrides { rules })
+    }
+
+    fn dependency_spec(&self, name: &str) -> Option<&str> {
+        [
+            &self.dependencies,
+            &self.dev_dependencies,
+            &self.optional_dependencies,
+            &self.peer_dependencies,
+        ]
+        .into_iter()
+        .flatten()
+        .find_map(|dependencies| dependencies.get(name))
+        .map(String::as_str)
+    }
+}
+
+impl OverrideRule {
+    fn applies_to(&self, name: &str, version: &str) -> bool {
+        if self.name != name {
+            return false;
+        }
+        let Some(selector) = &self.selector else {
+            return true;
+        };
+        match (spec_range(selector), version.parse()) {
+            (Some(range), Ok(version)) => range.satisfies(&version),
+            _ => false,
+        }
+    }
+}
+
+impl Overrides {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Walks the dependency tree of the lock file and returns every installed package
+    /// whose version doesn't match the override that applies to it.
+    ///
+    /// Overrides nested under a package only apply to its dependency tree,
+    /// and the most nested override wins. Values that aren't versions or ranges,
+    /// e.g. tags or git urls, can't be verified and are ignored.
+    pub fn verify(&self, lock_file: &PackageLockJson) -> Vec<OverrideViolation> {
+        let packages = lock_file.v2_packages();
+        let mut violations = Vec::new();
+        let mut visited = HashSet::new();
+        // packages to visit with the overrides of their ancestors, outermost first,
+        // each one with its index among the rules of the one above it. The walk starts
+        // from the root project and the workspaces.
+        let mut pending = packages
+            .keys()
+            .map(String::as_str)
+            .filter(|path| is_project_path(path))
+            .map(|path| (path, Vec::<(usize, &OverrideRule)>::new()))
+            .collect::<Vec<_>>();
+        while let Some((path, scopes)) = pending.pop() {
+            for edge in graph::edges(&packages, path) {
+                let Some(target) = edge.path else {
+                    continue;
+                };
+                let version = packages[target].version.as_str();
+                let rule = self.find_rule(&scopes, edge.name, version);
+                let mut child_scopes = scopes.clone();
+                if let Some((depth, index, rule)) = rule {
+                    if let Some(expected) = &rule.value {
+                        let satisfies = spec_range(expected).map(|range| {
+                            version
+                                .parse()
+                                .is_ok_and(|version| range.satisfies(&version))
+                        });
+                        if let (Some(false), Ok(name)) = (satisfies, PackageName::new(edge.name)) {
+                            let keys = scopes[..depth]
+                                .iter()
+                                .map(|(_, rule)| *rule)
+                                .chain([rule])
+                                .map(|rule| rule.key.as_str());
+                            violations.push(OverrideViolation {
+                                name,
+                                version: version.to_string(),
+                                path: target.to_string(),
+                                rule: keys.collect::<Vec<_>>().join(" > "),
+                                expected: expected.clone(),
+                            });
+                        }
+                    }
+                    if !rule.children.is_empty() {
+                        child_scopes.truncate(depth);
+                        child_scopes.push((index, rule));
+                    }
+                }
+                // the scopes are a path in the tree of rules, their indices tell them apart.
+                let key = child_scopes
+                    .iter()
+                    .map(|(index, _)| *index)
+                    .collect::<Vec<_>>();
+                if visited.insert((target, key)) {
+                    pending.push((target, child_scopes));
+                }
+            }
+        }
+        violations.sort();
+        violations.dedup();
+        violations
+    }
+
+    /// Returns the most nested rule applying to a package, the number of scopes above it
+    /// and its index among the rules of its scope.
+    fn find_rule<'a>(
+        &'a self,
+        scopes: &[(usize, &'a OverrideRule)],
+        name: &str,
+        version: &str,
+    ) -> Option<(usize, usize, &'a OverrideRule)> {
+        let find = |rules: &'a [OverrideRule]| {
+            rules
+                .iter()
+                .enumerate()
+                .find(|(_, rule)| rule.applies_to(name, version))
+        };
+        let nested = scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(depth, (_, scope))| {
+                find(&scope.children).map(|(index, rule)| (depth + 1, index, rule))
+            });
+        nested.or_else(|| find(&self.rules).map(|(index, rule)| (0, index, rule)))
+    }
+}
+
+fn parse_rules<'a>(
+    overrides: impl Iterator<Item = (&'a String, &'a Value)>,
+    manifest: &PackageJson,
+) -> Result<Vec<OverrideRule>, PackageLockJsonError> {
+    let mut rules = Vec::new();
+    for (key, value) in overrides {
+        if key == "." {
+            continue;
+        }
+        // the `@` of a scope is not a selector.
+        let (name, selector) = match key.get(1..).and_then(|rest| rest.split_once('@')) {
+            Some((name, selector)) => (&key[..name.len() + 1], Some(selector.to_string())),
+            None => (key.as_str(), None),
+        };
+        let (value, children) = match value {
+            Value::String(value) => (Some(value.as_str()), Vec::new()),
+            Value::Object(nested) => (
+                nested.get(".").and_then(Value::as_str),
+                parse_rules(nested.iter(), manifest)?,
+            ),
+            _ => {
+                return Err(PackageLockJsonError::OverridesError(format!(
+                    "Invalid override for {key}"
+                )))
+            }
+        };
+        let value = match value.map(|value| value.strip_prefix('$').ok_or(value)) {
+            Some(Ok(reference)) => {
+                let spec = manifest.dependency_spec(reference).ok_or_else(|| {
+                    PackageLockJsonError::OverridesError(format!(
+                        "Unable to resolve reference ${reference}"
+                    ))
+                })?;
+                Some(spec.to_string())
+            }
+            Some(Err(value)) => Some(value.to_string()),
+            None => None,
+        };
+        rules.push(OverrideRule {
+            key: key.clone(),
+            name: name.to_string(),
+            selector,
+            value,
+            children,
+        });
+    }
+    rules.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{parse, parse_package_json};
+
+    fn manifest(overrides: &str) -> PackageJson {
+        let content = std::fs::read_to_string("tests/package.json").unwrap();
+        let mut manifest = parse_package_json(content).unwrap();
+        manifest.overrides = serde_json::from_str(overrides).unwrap();
+        manifest
+    }
+
+    #[test]
+    fn parse_overrides_works() {
+        let overrides = manifest(
+            r#"{
+                "rxjs": "$rxjs",
+                "@babel/highlight@^7.0.0": { ".": "7.18.6", "js-tokens": "4.0.0" }
+            }"#,
+        )
+        .parse_overrides()
+        .unwrap();
+        let highlight = &overrides.rules[0];
+        assert_eq!(highlight.name, "@babel/highlight");
+        assert_eq!(highlight.selector.as_deref(), Some("^7.0.0"));
+        assert_eq!(highlight.value.as_deref(), Some("7.18.6"));
+        assert_eq!(highlight.children[0].name, "js-tokens");
+        assert_eq!(overrides.rules[1].value.as_deref(), Some("^7.8.0"));
+
+        let err = manifest(r#"{ "rxjs": "$missing" }"#).parse_overrides();
+        assert!(matches!(err, Err(PackageLockJsonError::OverridesError(_))));
+    }
+
+    #[test]
+    fn verify_overrides_works() {
+        let content = std::fs::read_to_string("tests/v3/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap();
+
+        // tslint and tsutils have their own tslib 1.x.
+        let overrides = manifest(r#"{ "tslib": "^2.0.0" }"#)
+            .parse_overrides()
+            .unwrap();
+        let paths = overrides
+            .verify(&lock_file)
+            .into_iter()
+            .map(|violation| violation.path)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "node_modules/tslint/node_modules/tslib",
+                "node_modules/tsutils/node_modules/tslib"
+            ]
+        );
+
+        // only the js-tokens of @babel/highlight is overridden.
+        let overrides = manifest(r#"{ "@babel/highlight": { "js-tokens": "3.0.2" } }"#)
+            .parse_overrides()
+            .unwrap();
+        let violations = overrides.verify(&lock_file);
+        assert_eq!(
+            violations,
+            [OverrideViolation {
+                name: "js-tokens".parse().unwrap(),
+                version: "4.0.0".to_string(),
+                path: "node_modules/@babel/highlight/node_modules/js-tokens".to_string(),
+                rule: "@babel/highlight > js-tokens".to_string(),
+                expected: "3.0.2".to_string(),
+            }]
+        );
+
+        // the selector doesn't match rxjs 7.
+        let overrides = manifest(r#"{ "rxjs@6": { "tslib": "^1.0.0" } }"#)
+            .parse_overrides()
+            .unwrap();
+        assert!(overrides.verify(&lock_file).is_empty());
+    }
+
+    #[test]
+    fn verify_overrides_in_workspaces_works() {
+        let content = std::fs::read_to_string("tests/cool-project/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap();
+
+        let overrides = manifest(r#"{ "uuid": "^99.0.0", "type-fest": "^99.0.0" }"#)
+            .parse_overrides()
+            .unwrap();
+        let paths = overrides
+            .verify(&lock_file)
+            .into_iter()
+            .map(|violation| violation.path)
+            .collect::<HashSet<_>>();
+        for path in [
+            "libs/lib1/node_modules/type-fest",
+            "libs/lib2/node_modules/type-fest",
+            "libs/lib4-web/node_modules/type-fest",
+            "libs/lib4/node_modules/type-fest",
+            "libs/telemetry/node_modules/uuid",
+            "node_modules/hasha/node_modules/type-fest",
+            "node_modules/type-fest",
+            "node_modules/uuid",
+        ] {
+            assert!(paths.contains(path), "{path} isn't reported");
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    is_false, semver::spec_range, serialize_sorted, Map, PackageLockJson, PackageLockJsonError,
+};
+
+/// A package.json manifest.
+/// Only the fields related to dependencies are modelled.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Eq, PartialEq)]
+pub struct PackageJson {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub dependencies: Option<Map<String, String>>,
+    #[serde(
+        rename = "devDependencies",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub dev_dependencies: Option<Map<String, String>>,
+    #[serde(
+        rename = "optionalDependencies",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub optional_dependencies: Option<Map<String, String>>,
+    #[serde(
+        rename = "peerDependencies",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub peer_dependencies: Option<Map<String, String>>,
+    #[serde(
+        rename = "peerDependenciesMeta",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub peer_dependencies_meta: Option<Map<String, PeerDependencyMeta>>,
+    #[serde(
+        rename = "bundleDependencies",
+        alias = "bundledDependencies",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub bundle_dependencies: Option<BundleDependencies>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspaces: Option<Workspaces>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub engines: Option<Map<String, String>>,
+    /// Kept as written, use [`PackageJson::parse_overrides`] to read them.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub overrides: Option<Map<String, serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bin: Option<Bin>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Eq, PartialEq)]
+pub struct PeerDependencyMeta {
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub optional: bool,
+}
+
+/// `bundleDependencies` is either a list of names or `true` to bundle every dependency.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum BundleDependencies {
+    All(bool),
+    List(Vec<String>),
+}
+
+/// `workspaces` is either a list of globs or an object with a `packages` list.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum Workspaces {
+    List(Vec<String>),
+    Object {
+        packages: Vec<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        nohoist: Option<Vec<String>>,
+    },
+}
+
+/// `bin` is either the path of a single command or the paths keyed by command name.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum Bin {
+    Single(String),
+    Map(Map<String, String>),
+}
+
+/// Difference between a package.json manifest and its lock file.
+#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum LockfileMismatch {
+    /// Declared in package.json but not installed.
+    Missing { name: String, range: String },
+    /// Declared by the root project of the lock file but not in package.json.
+    Extra { name: String },
+    /// Installed with a version package.json doesn't accept.
+    OutOfRange {
+        name: String,
+        range: String,
+        version: String,
+    },
+}
+
+impl Workspaces {
+    /// Returns the globs of the workspace folders.
+    pub fn patterns(&self) -> &[String] {
+        match self {
+            Self::List(patterns) => patterns,
+            Self::Object { packages, .. } => packages,
+        }
+    }
+}
+
+impl Bin {
+    /// Returns the path of every command keyed by its name.
+    /// A single command is named after the package, without its scope.
+    pub fn commands(&self, package_name: &str) -> Map<String, String> {
+        match self {
+            Self::Single(path) => {
+                let name = package_name.rsplit('/').next().unwrap_or(package_name);
+                Map::from([(name.to_string(), path.clone())])
+            }
+            Self::Map(commands) => commands.clone(),
+        }
+    }
+}
+
+impl PackageJson {
+    /// Returns the names of the bundled dependencies, sorted.
+    pub fn bundled_dependencies(&self) -> Vec<&str> {
+        let mut names = match &self.bundle_dependencies {
+            Some(BundleDependencies::All(true)) => self
+                .dependencies
+                .iter()
+                .flatten()
+                .map(|(name, _)| name.as_str())
+                .collect(),
+            Some(BundleDependencies::List(names)) => names.iter().map(String::as_str).collect(),
+            Some(BundleDependencies::All(false)) | None => Vec::new(),
+        };
+        names.sort_unstable();
+        names
+    }
+
+    /// Checks the manifest against its lock file, the way `npm ci` does.
+    ///
+    /// Missing optional dependencies and optional peers are fine.
+    /// Ranges are only checked for registry specs, not for tags, git, file or url ones.
+    /// v1 lock files don't record the dependencies of the root project,
+    /// so extra dependencies are only found in v2 and v3 files.
+    pub fn check_lock_file(&self, lock_file: &PackageLockJson) -> Vec<LockfileMismatch> {
+        let mut mismatches = Vec::new();
+        let declared = [
+            &self.dependencies,
+            &self.dev_dependencies,
+            &self.optional_dependencies,
+            &self.peer_dependencies,
+        ];
+        for (name, range) in declared.iter().copied().flatten().flatten() {
+            let Some(version) = installed_version(lock_file, name) else {
+                if !self.is_optional(name) {
+                    mismatches.push(LockfileMismatch::Missing {
+                        name: name.clone(),
+                        range: range.clone(),
+                    });
+                }
+                continue;
+            };
+            let Some(spec) = spec_range(range) else {
+                continue;
+            };
+            let satisfies = version
+                .parse()
+                .is_ok_and(|version| spec.satisfies(&version));
+            if !satisfies {
+                mismatches.push(LockfileMismatch::OutOfRange {
+                    name: name.clone(),
+                    range: range.clone(),
+                    version: version.to_string(),
+                });
+            }
+        }
+
+        let root = lock_file
+            .packages_by_path
+            .as_ref()
+            .and_then(|packages| packages.get(""));
+        if let Some(root) = root {
+            let locked = [
+                &root.dependencies,
+                &root.dev_dependencies,
+                &root.optional_dependencies,
+                &root.peer_dependencies,
+            ];
+            for name in locked.into_iter().flatten().flat_map(|map| map.keys()) {
+                if !declared
+                    .iter()
+                    .any(|map| map.as_ref().is_some_and(|map| map.contains_key(name)))
+                {
+                    mismatches.push(LockfileMismatch::Extra { name: name.clone() });
+                }
+            }
+        }
+        mismatches.sort();
+        mismatches.dedup();
+        mismatches
+    }
+
+    /// Returns whether a dependency may be missing: optional dependencies and optional peers.
+    fn is_optional(&self, name: &str) -> bool {
+        let is_optional_peer = self
+            .peer_dependencies_meta
+            .as_ref()
+            .and_then(|meta| meta.get(name))
+            .is_some_and(|meta| meta.optional);
+        let declared = |dependencies: &Option<Map<String, String>>| {
+            dependencies
+                .as_ref()
+                .is_some_and(|dependencies| dependencies.contains_key(name))
+        };
+        declared(&self.optional_dependencies)
+            || is_optional_peer
+                && !declared(&self.dependencies)
+                && !declared(&self.dev_dependencies)
+    }
+}
+
+/// Returns the version of the package installed at the top of `node_modules`.
+fn installed_version<'a>(lock_file: &'a PackageLockJson, name: &str) -> Option<&'a str> {
+    if let Some(packages) = &lock_file.packages_by_path {
+        let package = packages.get(&format!("node_modules/{name}"))?;
+        if package.link {
+            let folder = packages.get(package.resolved.as_deref()?)?;
+            return Some(&folder.version);
+        }
+        return Some(&package.version);
+    }
+    let dependency = lock_file.dependencies.as_ref()?.get(name)?;
+    // aliases are written npm:real-name@1.0.0 in v1 files.
+    let version = dependency
+        .version
+        .strip_prefix("npm:")
+        .and_then(|alias| alias.rsplit_once('@'))
+        .map_or(dependency.version.as_str(), |(_, version)| version);
+    Some(version)
+}
+
+/// Parses a package.json file.
+#[instrument(skip(content))]
+pub fn parse_package_json(
+    content: impl Into<String> + std::fmt::Debug,
+) -> Result<PackageJson, PackageLockJsonError> {
+    Ok(serde_json::from_str(&content.into())?)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn parse_package_json_works() {
+        let content = r#"{
+            "name": "@scope/tool",
+            "dependencies": { "a": "^1.0.0" },
+            "peerDependencies": { "react": ">=17" },
+            "peerDependenciesMeta": { "react": { "optional": true } },
+            "bundledDependencies": true,
+            "workspaces": { "packages": ["libs/*"] },
+            "engines": { "node": ">=18" },
+            "overrides": { "foo": { ".": "1.0.0", "bar": "2.0.0" } },
+            "bin": "./cli.js"
+        }"#;
+        let manifest = parse_package_json(content).unwrap();
+        assert_eq!(manifest.bundled_dependencies(), ["a"]);
+        assert_eq!(manifest.workspaces.unwrap().patterns(), ["libs/*"]);
+        assert!(manifest.peer_dependencies_meta.unwrap()["react"].optional);
+        assert_eq!(
+            manifest.bin.unwrap().commands("@scope/tool"),
+            Map::from([("tool".to_string(), "./cli.js".to_string())])
+        );
+        assert!(manifest.overrides.unwrap().contains_key("foo"));
+    }
+
+    #[test]
+    fn check_lock_file_works() {
+        let content = std::fs::read_to_string("tests/package.json").unwrap();
+        let manifest = parse_package_json(content).unwrap();
+        for path in [
+            "tests/v1/package-lock.json",
+            "tests/v2/package-lock.json",
+            "tests/v3/package-lock.json",
+        ] {
+            let lock_file = parse(std::fs::read_to_string(path).unwrap()).unwrap();
+            assert_eq!(manifest.check_lock_file(&lock_file), [], "{path}");
+        }
+    }
+
+    #[test]
+    fn check_lock_file_finds_mismatches() {
+        let content = std::fs::read_to_string("tests/package.json").unwrap();
+        let mut manifest = parse_package_json(content).unwrap();
+        let dependencies = manifest.dependencies.as_mut().unwrap();
+        dependencies.insert("rxjs".to_string(), "^8.0.0".to_string());
+        dependencies.insert("left-pad".to_string(), "^1.3.0".to_string());
+        manifest
+            .dev_dependencies
+            .as_mut()
+            .unwrap()
+            .retain(|name, _| name != "typescript");
+        manifest.optional_dependencies =
+            Some(Map::from([("fsevents".to_string(), "^2.0.0".to_string())]));
+
+        let content = std::fs::read_to_string("tests/v3/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap();
+        let mismatches = manifest.check_lock_file(&lock_file);
+        assert_eq!(
+            mismatches,
+            [
+                LockfileMismatch::Missing {
+                    name: "left-pad".to_string(),
+                    range: "^1.3.0".to_string()
+                },
+                LockfileMismatch::Extra {
+                    name: "typescript".to_string()
+                },
+                LockfileMismatch::OutOfRange {
+                    name: "rxjs".to_string(),
+                    range: "^8.0.0".to_string(),
+                    version: "7.8.0".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn check_lock_file_handles_v1_aliases() {
+        let manifest = parse_package_json(
+            r#"{ "name": "app", "dependencies": { "string-width-cjs": "npm:string-width@^4.2.0" } }"#,
+        )
+        .unwrap();
+        let lock_file = parse(
+            r#"{
+                "name": "app",
+                "lockfileVersion": 1,
+                "dependencies": {
+                    "string-width-cjs": { "version": "npm:string-width@4.2.3" }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(manifest.check_lock_file(&lock_file), []);
+    }
+}
@@ -0,0 +1,214 @@
+use std::{collections::HashSet, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graph, name::is_project_path, InstalledDependency, PackageLockJson, PackageLockJsonError,
+    V2Dependency,
+};
+
+/// Platform packages are installed in, e.g. `linux/x64/glibc`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Platform {
+    /// Operating system as node names it, e.g. `linux`, `darwin` or `win32`.
+    pub os: String,
+    /// Architecture as node names it, e.g. `x64` or `arm64`.
+    pub cpu: String,
+    /// C library, `glibc` or `musl`. Only checked in linux, and only if known.
+    pub libc: Option<String>,
+}
+
+impl Platform {
+    pub fn new(os: impl Into<String>, cpu: impl Into<String>, libc: Option<String>) -> Self {
+        Self {
+            os: os.into(),
+            cpu: cpu.into(),
+            libc,
+        }
+    }
+
+    /// Returns whether the `os`, `cpu` and `libc` of a package accept the platform,
+    /// the way npm checks them.
+    pub fn supports(&self, package: &V2Dependency) -> bool {
+        let libc = match &self.libc {
+            Some(libc) if self.os == "linux" => accepts(&package.libc, libc),
+            _ => true,
+        };
+        libc && accepts(&package.os, &self.os) && accepts(&package.cpu, &self.cpu)
+    }
+}
+
+/// Lists accept every value unless they name it negated, e.g. `!win32`,
+/// or only name other values.
+fn accepts(list: &Option<Vec<String>>, value: &str) -> bool {
+    let Some(list) = list else {
+        return true;
+    };
+    if list.is_empty() || list == &["any"] {
+        return true;
+    }
+    let mut negated = 0;
+    let mut matched = false;
+    for entry in list {
+        match entry.strip_prefix('!') {
+            Some(entry) if entry == value => return false,
+            Some(_) => negated += 1,
+            None => matched |= entry == value,
+        }
+    }
+    matched || negated == list.len()
+}
+
+impl FromStr for Platform {
+    type Err = PackageLockJsonError;
+
+    /// Parses `os/cpu` or `os/cpu/libc`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parts = value.split('/').collect::<Vec<_>>();
+        match parts[..] {
+            [os, cpu] if !os.is_empty() && !cpu.is_empty() => Ok(Self::new(os, cpu, None)),
+            [os, cpu, libc] if !os.is_empty() && !cpu.is_empty() && !libc.is_empty() => {
+                Ok(Self::new(os, cpu, Some(libc.to_string())))
+            }
+            _ => Err(PackageLockJsonError::PlatformError(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.os, self.cpu)?;
+        if let Some(libc) = &self.libc {
+            write!(f, "/{libc}")?;
+        }
+        Ok(())
+    }
+}
+
+impl PackageLockJson {
+    /// Returns the packages npm would install in the platform, sorted by path.
+    ///
+    /// Optional packages that don't support the platform are skipped,
+    /// and so are the optional packages only they depend on.
+    /// Required packages are always returned, npm fails to install them instead.
+    /// v1 files have no platform information, so every package is returned.
+    pub fn installed_for_platform(&self, platform: &Platform) -> Vec<InstalledDependency> {
+        let installed = self.installed_dependencies();
+        let Some(packages) = &self.packages_by_path else {
+            return installed;
+        };
+        let is_optional = |package: &V2Dependency| package.is_optional || package.is_dev_optional;
+        let unsupported = packages
+            .iter()
+            .filter(|(_, package)| is_optional(package) && !platform.supports(package))
+            .map(|(path, _)| path.as_str())
+            .collect::<HashSet<_>>();
+
+        // the root project and the workspaces.
+        let mut pending = packages
+            .keys()
+            .map(String::as_str)
+            .filter(|path| is_project_path(path))
+            .collect::<Vec<_>>();
+        let mut reachable = pending.iter().copied().collect::<HashSet<_>>();
+        while let Some(path) = pending.pop() {
+            for edge in graph::edges(packages, path) {
+                let Some(target) = edge.path else {
+                    continue;
+                };
+                if !unsupported.contains(target) && reachable.insert(target) {
+                    pending.push(target);
+                }
+            }
+        }
+
+        installed
+            .into_iter()
+            .filter(|dependency| {
+                let package = &packages[dependency.path.as_str()];
+                !is_optional(package) || reachable.contains(dependency.path.as_str())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn platform_from_str_works() {
+        let platform = "linux/x64/glibc".parse::<Platform>().unwrap();
+        assert_eq!(
+            platform,
+            Platform::new("linux", "x64", Some("glibc".to_string()))
+        );
+        assert_eq!(platform.to_string(), "linux/x64/glibc");
+        assert!("linux".parse::<Platform>().is_err());
+    }
+
+    #[test]
+    fn supports_works() {
+        let platform = "linux/x64/musl".parse::<Platform>().unwrap();
+        let package = |os: &[&str], libc: &[&str]| V2Dependency {
+            os: Some(os.iter().map(ToString::to_string).collect()),
+            libc: Some(libc.iter().map(ToString::to_string).collect()),
+            ..V2Dependency::default()
+        };
+        assert!(platform.supports(&package(&["linux"], &["musl"])));
+        assert!(platform.supports(&package(&["!win32"], &[])));
+        assert!(!platform.supports(&package(&["!linux"], &[])));
+        assert!(!platform.supports(&package(&["darwin", "win32"], &[])));
+        assert!(!platform.supports(&package(&["linux"], &["glibc"])));
+    }
+
+    #[test]
+    fn installed_for_platform_works() {
+        let content = std::fs::read_to_string("tests/cool-project/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap();
+        let platform = "linux/x64/glibc".parse().unwrap();
+        let installed = lock_file.installed_for_platform(&platform);
+        let has = |path: &str| installed.iter().any(|dependency| dependency.path == path);
+        assert!(has("node_modules/@esbuild/linux-x64"));
+        assert!(has("node_modules/@nx/nx-linux-x64-gnu"));
+        assert!(!has("node_modules/@esbuild/darwin-arm64"));
+        assert!(!has("node_modules/fsevents"));
+
+        // required packages are all there.
+        let required = lock_file
+            .installed_dependencies()
+            .into_iter()
+            .filter(|dependency| !dependency.is_optional && !dependency.is_dev_optional)
+            .all(|dependency| installed.contains(&dependency));
+        assert!(required);
+    }
+
+    #[test]
+    fn installed_for_platform_checks_libc() {
+        let content = r#"{
+            "name": "native",
+            "lockfileVersion": 3,
+            "packages": {
+                "": { "optionalDependencies": { "bin-gnu": "1.0.0", "bin-musl": "1.0.0" } },
+                "node_modules/bin-gnu": {
+                    "version": "1.0.0", "optional": true, "os": ["linux"], "libc": ["glibc"],
+                    "dependencies": { "gnu-helper": "1.0.0" }
+                },
+                "node_modules/gnu-helper": { "version": "1.0.0", "optional": true },
+                "node_modules/bin-musl": {
+                    "version": "1.0.0", "optional": true, "os": ["linux"], "libc": ["musl"]
+                }
+            }
+        }"#;
+        let lock_file = parse(content).unwrap();
+        let platform = "linux/x64/musl".parse().unwrap();
+        let paths = lock_file
+            .installed_for_platform(&platform)
+            .into_iter()
+            .map(|dependency| dependency.path)
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["node_modules/bin-musl"]);
+    }
+}
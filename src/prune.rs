@@ -0,0 +1,125 @@
+use std::collections::HashSet;
+
+use crate::{normalize_packages, Map, PackageLockJson, V1Dependency, V2Dependency};
+
+impl PackageLockJson {
+    /// Returns the lock file with only what `npm ci --omit=dev --omit=optional` installs.
+    ///
+    /// Packages flagged `dev`, `optional` or `devOptional` are removed,
+    /// along with the packages nested in them and the links pointing to them.
+    /// The root project and workspace entries are kept as they are,
+    /// so the file still matches their package.json manifests.
+    pub fn to_production(&self) -> Self {
+        let packages_by_path = self.packages_by_path.as_ref().map(production_packages);
+        Self {
+            packages: packages_by_path.as_ref().map(normalize_packages),
+            packages_by_path,
+            dependencies: self.dependencies.as_ref().map(production_dependencies),
+            ..self.clone()
+        }
+    }
+}
+
+fn is_production(package: &V2Dependency) -> bool {
+    !package.is_dev && !package.is_optional && !package.is_dev_optional
+}
+
+fn production_packages(packages: &Map<String, V2Dependency>) -> Map<String, V2Dependency> {
+    let removed = packages
+        .iter()
+        .filter(|(path, package)| !path.is_empty() && !is_production(package))
+        .map(|(path, _)| path.as_str())
+        .collect::<HashSet<_>>();
+    let is_removed = |path: &str| {
+        // the package or one it is nested in.
+        removed.contains(path)
+            || path
+                .match_indices("/node_modules/")
+                .any(|(end, _)| removed.contains(&path[..end]))
+    };
+    packages
+        .iter()
+        .filter(|(path, package)| {
+            if is_removed(path) {
+                return false;
+            }
+            let target = package.resolved.as_deref().filter(|_| package.link);
+            !target.is_some_and(is_removed)
+        })
+        .map(|(path, package)| (path.clone(), package.clone()))
+        .collect()
+}
+
+fn production_dependencies(dependencies: &Map<String, V1Dependency>) -> Map<String, V1Dependency> {
+    dependencies
+        .iter()
+        .filter(|(_, dependency)| !dependency.is_dev && !dependency.is_optional)
+        .map(|(name, dependency)| {
+            let nested = dependency
+                .dependencies
+                .as_ref()
+                .map(production_dependencies)
+                .filter(|nested| !nested.is_empty());
+            let dependency = V1Dependency {
+                dependencies: nested,
+                ..dependency.clone()
+            };
+            (name.clone(), dependency)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{graph, name::is_project_path, parse};
+
+    #[test]
+    fn to_production_works() {
+        let content = std::fs::read_to_string("tests/v2/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap().to_production();
+
+        let mut paths = lock_file
+            .packages_by_path
+            .as_ref()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, ["", "node_modules/rxjs", "node_modules/tslib"]);
+        let mut names = lock_file
+            .dependencies
+            .as_ref()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["rxjs", "tslib"]);
+
+        let json = lock_file.to_json().unwrap();
+        assert_eq!(parse(json).unwrap(), lock_file);
+    }
+
+    #[test]
+    fn to_production_removes_orphans() {
+        let content = std::fs::read_to_string("tests/cool-project/package-lock.json").unwrap();
+        let lock_file = parse(content).unwrap();
+        let production = lock_file.to_production();
+        let packages = production.packages_by_path.as_ref().unwrap();
+        assert!(packages.len() < lock_file.packages_by_path.as_ref().unwrap().len());
+        assert!(!packages.contains_key("node_modules/fsevents"));
+
+        // every remaining package has its dependencies installed.
+        for (path, package) in packages {
+            assert!(!package.is_dev && !package.is_optional && !package.is_dev_optional);
+            if package.link || is_project_path(path) {
+                continue;
+            }
+            for edge in graph::edges(packages, path) {
+                if !edge.is_optional && !edge.is_peer {
+                    assert!(edge.path.is_some(), "{} of {}", edge.name, path);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,388 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    InstalledDependency, Map, PackageLockJsonError, SimpleDependency, V1Dependency, V2Dependency,
+};
+
+/// A package url, e.g. `pkg:npm/%40babel/highlight@7.18.6`, the id SBOM
+/// and vulnerability tools use for packages.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Purl {
+    /// Package type, `npm` for the packages of a lock file.
+    pub kind: String,
+    /// Scope of the package with its `@`, e.g. `@babel`.
+    pub namespace: Option<String>,
+    pub name: String,
+    pub version: Option<String>,
+    /// Extra data, e.g. `vcs_url` or `download_url` for packages not installed from the registry.
+    pub qualifiers: Map<String, String>,
+    /// Path inside the package.
+    pub subpath: Option<String>,
+}
+
+impl Purl {
+    /// Returns the purl of an npm package, `name` being the full name, e.g. `@babel/highlight`.
+    /// The scope and name are lowercased as the purl spec requires for npm.
+    pub fn npm(name: &str, version: Option<&str>) -> Self {
+        let (namespace, name) = match name.split_once('/') {
+            Some((scope, name)) if scope.starts_with('@') => (Some(scope.to_lowercase()), name),
+            _ => (None, name),
+        };
+        Self {
+            kind: "npm".to_string(),
+            namespace,
+            name: name.to_lowercase(),
+            version: version
+                .filter(|version| !version.is_empty())
+                .map(ToString::to_string),
+            qualifiers: Map::new(),
+            subpath: None,
+        }
+    }
+
+    /// Returns the purl of an npm package, with a qualifier telling where it comes from
+    /// if `resolved` isn't a registry tarball.
+    fn npm_resolved(name: &str, version: &str, resolved: Option<&str>) -> Self {
+        // git dependencies of v1 files have the url as version.
+        let (version, resolved) = match resolved {
+            None if version.contains(':') => (None, Some(version)),
+            _ => (Some(version), resolved),
+        };
+        let mut purl = Self::npm(name, version);
+        if let Some((key, url)) = resolved.and_then(source_qualifier) {
+            purl.qualifiers.insert(key.to_string(), url.to_string());
+        }
+        purl
+    }
+
+    /// Returns the full name of the package, e.g. `@babel/highlight`.
+    pub fn full_name(&self) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{namespace}/{}", self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Returns the qualifier for a `resolved` value that isn't a registry tarball:
+/// `vcs_url` for git and `download_url` for other remote tarballs.
+/// Local folders and files get none.
+fn source_qualifier(resolved: &str) -> Option<(&'static str, &str)> {
+    if resolved.starts_with("git") || resolved.starts_with("github:") {
+        return Some(("vcs_url", resolved));
+    }
+    if !resolved.starts_with("http://") && !resolved.starts_with("https://") {
+        return None;
+    }
+    // registry tarballs look like `<registry>/<name>/-/<name>-<version>.tgz`.
+    let path = resolved.split(['?', '#']).next().unwrap_or(resolved);
+    if path.contains("/-/") && path.ends_with(".tgz") {
+        None
+    } else {
+        Some(("download_url", resolved))
+    }
+}
+
+impl FromStr for Purl {
+    type Err = PackageLockJsonError;
+
+    /// Parses `pkg:type/namespace/name@version?qualifiers#subpath`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || PackageLockJsonError::PurlError(value.to_string());
+        let (rest, subpath) = match value.split_once('#') {
+            Some((rest, subpath)) => (rest, Some(subpath)),
+            None => (value, None),
+        };
+        let (rest, qualifiers) = match rest.split_once('?') {
+            Some((rest, qualifiers)) => (rest, Some(qualifiers)),
+            None => (rest, None),
+        };
+        let (scheme, rest) = rest.split_once(':').ok_or_else(invalid)?;
+        if !scheme.eq_ignore_ascii_case("pkg") {
+            return Err(invalid());
+        }
+        let rest = rest.trim_matches('/');
+        let (kind, rest) = rest.split_once('/').ok_or_else(invalid)?;
+        let rest = rest.trim_end_matches('/');
+        let (namespace, name) = match rest.rsplit_once('/') {
+            Some((namespace, name)) => (Some(namespace), name),
+            None => (None, rest),
+        };
+        let (name, version) = match name.split_once('@') {
+            Some((name, version)) => (name, Some(decode(version).ok_or_else(invalid)?)),
+            None => (name, None),
+        };
+        if kind.is_empty() || name.is_empty() {
+            return Err(invalid());
+        }
+        let namespace = match namespace {
+            Some(namespace) => {
+                let segments = namespace
+                    .split('/')
+                    .filter(|segment| !segment.is_empty())
+                    .map(decode)
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(invalid)?;
+                Some(segments.join("/"))
+            }
+            None => None,
+        };
+        let mut parsed_qualifiers = Map::new();
+        for qualifier in qualifiers
+            .into_iter()
+            .flat_map(|qualifiers| qualifiers.split('&'))
+        {
+            let (key, value) = qualifier.split_once('=').ok_or_else(invalid)?;
+            let value = decode(value).ok_or_else(invalid)?;
+            if !value.is_empty() {
+                parsed_qualifiers.insert(key.to_ascii_lowercase(), value);
+            }
+        }
+        let subpath = match subpath {
+            Some(subpath) => {
+                let segments = subpath
+                    .split('/')
+                    .filter(|segment| !segment.is_empty() && *segment != "." && *segment != "..")
+                    .map(decode)
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(invalid)?;
+                (!segments.is_empty()).then(|| segments.join("/"))
+            }
+            None => None,
+        };
+        let kind = kind.to_ascii_lowercase();
+        let mut name = decode(name).ok_or_else(invalid)?;
+        let mut namespace = namespace;
+        if kind == "npm" {
+            name = name.to_lowercase();
+            namespace = namespace.map(|namespace| namespace.to_lowercase());
+        }
+        Ok(Self {
+            kind,
+            namespace,
+            name,
+            version,
+            qualifiers: parsed_qualifiers,
+            subpath,
+        })
+    }
+}
+
+impl fmt::Display for Purl {
+    /// Writes the canonical form: percent encoded, with sorted qualifiers.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pkg:{}/", self.kind)?;
+        if let Some(namespace) = &self.namespace {
+            for segment in namespace.split('/') {
+                write!(f, "{}/", encode(segment, ""))?;
+            }
+        }
+        f.write_str(&encode(&self.name, ""))?;
+        if let Some(version) = &self.version {
+            write!(f, "@{}", encode(version, ""))?;
+        }
+        let mut qualifiers = self
+            .qualifiers
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .collect::<Vec<_>>();
+        qualifiers.sort();
+        for (i, (key, value)) in qualifiers.into_iter().enumerate() {
+            let separator = if i == 0 { '?' } else { '&' };
+            write!(f, "{separator}{key}={}", encode(value, ":/"))?;
+        }
+        if let Some(subpath) = &self.subpath {
+            write!(f, "#{}", encode(subpath, "/"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Percent encodes everything but letters, digits, `-._~` and the `safe` characters.
+fn encode(value: &str, safe: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric()
+            || b"-._~".contains(&byte)
+            || safe.as_bytes().contains(&byte)
+        {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+fn decode(value: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+impl V1Dependency {
+    /// Returns the purl of the package, `name` being the key of the entry.
+    /// Aliased packages get the purl of the real package.
+    pub fn to_purl(&self, name: &str) -> Purl {
+        let (name, version) = v1_alias(&self.version).unwrap_or((name, &self.version));
+        Purl::npm_resolved(name, version, self.resolved.as_deref())
+    }
+}
+
+/// Splits the version of an aliased v1 entry, `npm:real-name@1.0.0`.
+fn v1_alias(version: &str) -> Option<(&str, &str)> {
+    version.strip_prefix("npm:")?.rsplit_once('@')
+}
+
+impl V2Dependency {
+    /// Returns the purl of the package, `name` being the name it's installed as.
+    /// Aliased packages get the purl of the real package.
+    pub fn to_purl(&self, name: &str) -> Purl {
+        let name = self.name.as_deref().unwrap_or(name);
+        Purl::npm_resolved(name, &self.version, self.resolved.as_deref())
+    }
+}
+
+impl InstalledDependency {
+    /// Returns the purl of the package. Aliased packages get the purl of the real package.
+    pub fn to_purl(&self) -> Purl {
+        let name = self.real_name.as_ref().unwrap_or(&self.name).as_str();
+        Purl::npm_resolved(name, &self.version, self.resolved.as_deref())
+    }
+}
+
+impl SimpleDependency {
+    pub fn to_purl(&self) -> Purl {
+        Purl::npm(self.name.as_str(), Some(&self.version))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn purl_round_trip_works() {
+        let purl = "pkg:npm/%40babel/highlight@7.18.6".parse::<Purl>().unwrap();
+        assert_eq!(purl.namespace.as_deref(), Some("@babel"));
+        assert_eq!(purl.name, "highlight");
+        assert_eq!(purl.version.as_deref(), Some("7.18.6"));
+        assert_eq!(purl.full_name(), "@babel/highlight");
+        assert_eq!(purl.to_string(), "pkg:npm/%40babel/highlight@7.18.6");
+        assert_eq!(purl, Purl::npm("@babel/highlight", Some("7.18.6")));
+
+        // npm names are lowercased.
+        assert_eq!(
+            Purl::npm("JSONStream", Some("1.3.5")).to_string(),
+            "pkg:npm/jsonstream@1.3.5"
+        );
+        assert_eq!(
+            "pkg:npm/JSONStream@1.3.5".parse::<Purl>().unwrap().name,
+            "jsonstream"
+        );
+        let purl = Purl::npm("@Types/Node", Some("18.0.0"));
+        assert_eq!(purl.to_string(), "pkg:npm/%40types/node@18.0.0");
+        assert_eq!(
+            "pkg:npm/%40Types/Node@18.0.0".parse::<Purl>().unwrap(),
+            purl
+        );
+        assert_eq!(purl.to_string().parse::<Purl>().unwrap(), purl);
+
+        // not encoded and not canonical.
+        let purl = "PKG:NPM/@babel/highlight@7.18.6?b=2&A=1#/lib/index.js"
+            .parse::<Purl>()
+            .unwrap();
+        assert_eq!(purl.full_name(), "@babel/highlight");
+        assert_eq!(purl.subpath.as_deref(), Some("lib/index.js"));
+        assert_eq!(
+            purl.to_string(),
+            "pkg:npm/%40babel/highlight@7.18.6?a=1&b=2#lib/index.js"
+        );
+
+        let purl = "pkg:npm/left-pad".parse::<Purl>().unwrap();
+        assert_eq!((purl.namespace, purl.version), (None, None));
+        for invalid in [
+            "npm/left-pad",
+            "pkg:npm",
+            "pkg:npm/",
+            "pkg:npm/a%2",
+            "pkg:npm/a?b",
+        ] {
+            assert!(invalid.parse::<Purl>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn to_purl_works() {
+        let content = r#"{
+            "name": "app",
+            "lockfileVersion": 3,
+            "packages": {
+                "": { "dependencies": { "a": "^1.0.0", "b": "github:user/b", "c": "npm:@scope/c@^1.0.0" } },
+                "node_modules/a": {
+                    "version": "1.0.0",
+                    "resolved": "https://registry.npmjs.org/a/-/a-1.0.0.tgz"
+                },
+                "node_modules/b": {
+                    "version": "2.0.0",
+                    "resolved": "git+ssh://git@github.com/user/b.git#abc123"
+                },
+                "node_modules/c": {
+                    "name": "@scope/c",
+                    "version": "1.0.0",
+                    "resolved": "https://example.com/files/c.tgz"
+                }
+            }
+        }"#;
+        let lock_file = parse(content).unwrap();
+        let purls = lock_file
+            .installed_dependencies()
+            .iter()
+            .map(|dependency| dependency.to_purl().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            purls,
+            [
+                "pkg:npm/a@1.0.0",
+                "pkg:npm/b@2.0.0?vcs_url=git%2Bssh://git%40github.com/user/b.git%23abc123",
+                "pkg:npm/%40scope/c@1.0.0?download_url=https://example.com/files/c.tgz",
+            ]
+        );
+        let v1_purls = lock_file
+            .to_v1()
+            .installed_dependencies()
+            .iter()
+            .map(|dependency| dependency.to_purl().to_string())
+            .collect::<Vec<_>>();
+        // v1 files keep the alias in the version.
+        assert_eq!(v1_purls[2], purls[2]);
+
+        let c = lock_file.package("node_modules/c").unwrap().to_purl("c");
+        assert_eq!(
+            c.to_string(),
+            "pkg:npm/%40scope/c@1.0.0?download_url=https://example.com/files/c.tgz"
+        );
+        let qualifiers = &c.to_string().parse::<Purl>().unwrap().qualifiers;
+        assert_eq!(
+            qualifiers["download_url"],
+            "https://example.com/files/c.tgz"
+        );
+
+        let b = lock_file.to_v1().dependencies.unwrap()["b"].to_purl("b");
+        assert_eq!(
+            b.qualifiers["vcs_url"],
+            "git+ssh://git@github.com/user/b.git#abc123"
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{is_false, serialize_sorted, Map, PackageLockJsonError, PackageName, Version};
+
+/// Registry metadata of a package, the document npm serves at `/<name>`.
+/// Only the fields related to dependencies are modelled.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Eq, PartialEq)]
+pub struct Packument {
+    pub name: String,
+    /// Tags pointing to a version, e.g. `latest`.
+    #[serde(rename = "dist-tags", default)]
+    pub dist_tags: Map<String, String>,
+    /// Every published version keyed by its version.
+    #[serde(default)]
+    pub versions: Map<String, PackumentVersion>,
+}
+
+/// Manifest of a published version.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Eq, PartialEq)]
+pub struct PackumentVersion {
+    pub name: String,
+    pub version: String,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub dependencies: Option<Map<String, String>>,
+    #[serde(
+        rename = "optionalDependencies",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub optional_dependencies: Option<Map<String, String>>,
+    #[serde(
+        rename = "peerDependencies",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_sorted"
+    )]
+    pub peer_dependencies: Option<Map<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dist: Option<Dist>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<String>,
+    #[serde(rename = "hasInstallScript", default, skip_serializing_if = "is_false")]
+    pub has_install_script: bool,
+}
+
+/// Where the tarball of a version is and its checksums.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Eq, PartialEq)]
+pub struct Dist {
+    pub tarball: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shasum: Option<String>,
+}
+
+impl Packument {
+    /// Returns the published versions that are valid semver, lowest first.
+    pub fn sorted_versions(&self) -> Vec<(Version, &PackumentVersion)> {
+        let mut versions = self
+            .versions
+            .iter()
+            .filter_map(|(version, manifest)| Some((version.parse::<Version>().ok()?, manifest)))
+            .collect::<Vec<_>>();
+        versions.sort_by(|a, b| a.0.cmp(&b.0));
+        versions
+    }
+}
+
+/// Somewhere packuments are read from.
+pub trait PackumentSource {
+    /// Returns the packument of a package, `None` if the source doesn't have it.
+    fn packument(&self, name: &str) -> Result<Option<Packument>, PackageLockJsonError>;
+}
+
+/// Packuments kept in memory, keyed by package name.
+impl PackumentSource for Map<String, Packument> {
+    fn packument(&self, name: &str) -> Result<Option<Packument>, PackageLockJsonError> {
+        Ok(self.get(name).cloned())
+    }
+}
+
+/// Directory with a `<name>.json` packument per package,
+/// scoped packages in a folder per scope, e.g. `@babel/highlight.json`.
+/// Names are validated first, so nothing outside the directory is read.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PackumentDir {
+    pub path: PathBuf,
+}
+
+impl PackumentDir {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl PackumentSource for PackumentDir {
+    fn packument(&self, name: &str) -> Result<Option<Packument>, PackageLockJsonError> {
+        let name = PackageName::new(name)?;
+        let folder = match name.scope() {
+            Some(scope) => self.path.join(scope),
+            None => self.path.clone(),
+        };
+        let path = folder.join(format!("{}.json", name.name()));
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => return Err(PackageLockJsonError::IoError { path, source }),
+        };
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|source| PackageLockJsonError::FileParseError { path, source })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn packument_dir_works() {
+        let registry = PackumentDir::new("tests/registry");
+        let packument = registry.packument("@scope/c").unwrap().unwrap();
+        assert_eq!(packument.name, "@scope/c");
+        assert_eq!(packument.dist_tags["latest"], "2.0.0");
+        let versions = packument
+            .sorted_versions()
+            .into_iter()
+            .map(|(version, _)| version.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(versions, ["1.0.0", "2.0.0", "3.0.0-beta.1"]);
+        assert!(registry.packument("missing").unwrap().is_none());
+        for name in ["../registry/a", "@scope/../../a", "/etc/passwd", ".."] {
+            assert!(
+                matches!(
+                    registry.packument(name),
+                    Err(PackageLockJsonError::PackageNameError(_))
+                ),
+                "{name}"
+            );
+        }
+    }
+}
@@ -0,0 +1,587 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use crate::PackageLockJsonError;
+
+/// A semver version, compared the way npm does it.
+///
+/// Build metadata is kept but, as semver requires, ignored when comparing versions,
+/// so `1.0.0+a` and `1.0.0+b` are equal.
+#[derive(Debug, Clone, Default)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    pub pre: Vec<Prerelease>,
+    pub build: Vec<String>,
+}
+
+/// Identifier of the prerelease part of a version, e.g. `beta` or `1` in `1.0.0-beta.1`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Prerelease {
+    Numeric(u64),
+    Alphanumeric(String),
+}
+
+/// An npm version range, e.g. `^1.2.3 || >=2.0.0 <3.0.0`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VersionRange {
+    raw: String,
+    /// Sets of comparators joined by `||`.
+    sets: Vec<Vec<Comparator>>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Op {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Comparator {
+    op: Op,
+    version: Version,
+}
+
+/// A version where any part may be missing or a wildcard, e.g. `1.2` or `1.x`.
+#[derive(Debug, Clone, Default)]
+struct Partial {
+    major: Option<u64>,
+    minor: Option<u64>,
+    patch: Option<u64>,
+    pre: Vec<Prerelease>,
+}
+
+fn invalid(value: &str) -> PackageLockJsonError {
+    PackageLockJsonError::SemverError(value.to_string())
+}
+
+impl Version {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_prerelease(&self) -> bool {
+        !self.pre.is_empty()
+    }
+
+    /// Lowest prerelease of a version, used as exclusive upper bound.
+    fn lowest(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            pre: vec![Prerelease::Numeric(0)],
+            ..Self::new(major, minor, patch)
+        }
+    }
+}
+
+impl FromStr for Version {
+    type Err = PackageLockJsonError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let partial = Partial::parse(value)?;
+        match (partial.major, partial.minor, partial.patch) {
+            (Some(major), Some(minor), Some(patch)) => {
+                let build = value
+                    .split_once('+')
+                    .map(|(_, build)| build.split('.').map(ToString::to_string).collect())
+                    .unwrap_or_default();
+                Ok(Self {
+                    major,
+                    minor,
+                    patch,
+                    pre: partial.pre,
+                    build,
+                })
+            }
+            _ => Err(invalid(value)),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.pre.is_empty() {
+            let pre = self.pre.iter().map(ToString::to_string).collect::<Vec<_>>();
+            write!(f, "-{}", pre.join("."))?;
+        }
+        if !self.build.is_empty() {
+            write!(f, "+{}", self.build.join("."))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Prerelease {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Numeric(value) => write!(f, "{value}"),
+            Self::Alphanumeric(value) => f.write_str(value),
+        }
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (self.pre.is_empty(), other.pre.is_empty()) {
+                (true, true) => Ordering::Equal,
+                // a release is greater than any of its prereleases.
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => self.pre.cmp(&other.pre),
+            })
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl Hash for Version {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // the same fields as the ordering.
+        (self.major, self.minor, self.patch, &self.pre).hash(state);
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Prerelease {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Numeric(a), Self::Numeric(b)) => a.cmp(b),
+            (Self::Numeric(_), Self::Alphanumeric(_)) => Ordering::Less,
+            (Self::Alphanumeric(_), Self::Numeric(_)) => Ordering::Greater,
+            (Self::Alphanumeric(a), Self::Alphanumeric(b)) => a.cmp(b),
+        }
+    }
+}
+
+impl PartialOrd for Prerelease {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Partial {
+    /// Parses `1`, `1.2`, `1.x`, `v1.2.3-beta.1+build` and alike.
+    fn parse(value: &str) -> Result<Self, PackageLockJsonError> {
+        let trimmed = value.trim().trim_start_matches('=').trim_start_matches('v');
+        let trimmed = trimmed
+            .split_once('+')
+            .map_or(trimmed, |(version, _)| version);
+        let (numbers, pre) = match trimmed.split_once('-') {
+            Some((numbers, pre)) => (numbers, Some(pre)),
+            None => (trimmed, None),
+        };
+        let mut parts = numbers.split('.');
+        let mut next = || -> Result<Option<u64>, PackageLockJsonError> {
+            match parts.next() {
+                None | Some("x" | "X" | "*") => Ok(None),
+                Some(part) => part.parse().map(Some).map_err(|_| invalid(value)),
+            }
+        };
+        let major = next()?;
+        let minor = next()?;
+        let patch = next()?;
+        if parts.next().is_some() || numbers.is_empty() && pre.is_some() {
+            return Err(invalid(value));
+        }
+        let pre = match pre {
+            Some(pre) => pre
+                .split('.')
+                .map(|identifier| match identifier.parse() {
+                    Ok(number) => Ok(Prerelease::Numeric(number)),
+                    Err(_) if !identifier.is_empty() => {
+                        Ok(Prerelease::Alphanumeric(identifier.to_string()))
+                    }
+                    Err(_) => Err(invalid(value)),
+                })
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        Ok(Self {
+            major,
+            minor: major.and(minor),
+            patch: major.and(minor).and(patch),
+            pre,
+        })
+    }
+
+    /// Lowest version matching the partial.
+    fn floor(&self) -> Version {
+        Version {
+            pre: if self.patch.is_some() {
+                self.pre.clone()
+            } else {
+                Vec::new()
+            },
+            ..Version::new(
+                self.major.unwrap_or_default(),
+                self.minor.unwrap_or_default(),
+                self.patch.unwrap_or_default(),
+            )
+        }
+    }
+
+    /// Exclusive upper bound of an x-range, `None` if there is none,
+    /// like for `*` or when the next version would overflow.
+    fn ceiling(&self) -> Option<Version> {
+        match (self.major, self.minor) {
+            (None, _) => None,
+            (Some(major), None) => Some(Version::lowest(major.checked_add(1)?, 0, 0)),
+            (Some(major), Some(minor)) => Some(Version::lowest(major, minor.checked_add(1)?, 0)),
+        }
+    }
+}
+
+impl Comparator {
+    fn new(op: Op, version: Version) -> Self {
+        Self { op, version }
+    }
+
+    fn matches(&self, version: &Version) -> bool {
+        let ordering = version.cmp(&self.version);
+        match self.op {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl VersionRange {
+    /// Returns whether the version is in the range.
+    /// Prereleases only match if a comparator of the same set has a prerelease
+    /// of the same `major.minor.patch`, like npm does.
+    pub fn satisfies(&self, version: &Version) -> bool {
+        self.sets.iter().any(|set| {
+            set.iter().all(|comparator| comparator.matches(version))
+                && (!version.is_prerelease()
+                    || set.iter().any(|comparator| {
+                        comparator.version.is_prerelease()
+                            && (
+                                comparator.version.major,
+                                comparator.version.minor,
+                                comparator.version.patch,
+                            ) == (version.major, version.minor, version.patch)
+                    }))
+        })
+    }
+
+    /// Returns the highest version in the range.
+    pub fn max_satisfying<'a>(
+        &self,
+        versions: impl IntoIterator<Item = &'a Version>,
+    ) -> Option<&'a Version> {
+        versions
+            .into_iter()
+            .filter(|version| self.satisfies(version))
+            .max()
+    }
+
+    fn parse_set(set: &str) -> Result<Vec<Comparator>, PackageLockJsonError> {
+        let set = set.trim();
+        // hyphen ranges: 1.2.3 - 2.3.4
+        if let Some((from, to)) = set.split_once(" - ") {
+            let from = Partial::parse(from)?;
+            let to = Partial::parse(to)?;
+            let mut comparators = Vec::new();
+            if from.major.is_some() {
+                comparators.push(Comparator::new(Op::Ge, from.floor()));
+            }
+            if to.patch.is_some() {
+                comparators.push(Comparator::new(Op::Le, to.floor()));
+            } else if let Some(ceiling) = to.ceiling() {
+                comparators.push(Comparator::new(Op::Lt, ceiling));
+            }
+            return Ok(comparators);
+        }
+
+        // operators may be separated from their version: >= 1.2.3
+        let mut tokens = Vec::new();
+        let mut pending = String::new();
+        for token in set.split_whitespace() {
+            if token
+                .chars()
+                .all(|c| matches!(c, '<' | '>' | '=' | '~' | '^'))
+            {
+                pending.push_str(token);
+            } else {
+                tokens.push(format!("{pending}{token}"));
+                pending.clear();
+            }
+        }
+
+        let mut comparators = Vec::new();
+        for token in tokens {
+            comparators.extend(Self::parse_comparator(&token)?);
+        }
+        Ok(comparators)
+    }
+
+    fn parse_comparator(token: &str) -> Result<Vec<Comparator>, PackageLockJsonError> {
+        let (op, version) = if let Some(version) = token.strip_prefix("~>") {
+            ("~", version)
+        } else {
+            let end = token
+                .find(|c: char| !matches!(c, '<' | '>' | '=' | '~' | '^'))
+                .unwrap_or(token.len());
+            token.split_at(end)
+        };
+        let partial = Partial::parse(version)?;
+        let floor = partial.floor();
+        let comparators = match op {
+            "" | "=" => match (partial.major, partial.patch) {
+                (None, _) => Vec::new(),
+                (_, Some(_)) => vec![Comparator::new(Op::Eq, floor)],
+                (_, None) => {
+                    let mut comparators = vec![Comparator::new(Op::Ge, floor)];
+                    comparators.extend(
+                        partial
+                            .ceiling()
+                            .map(|ceiling| Comparator::new(Op::Lt, ceiling)),
+                    );
+                    comparators
+                }
+            },
+            "~" => {
+                let ceiling = partial.ceiling();
+                let mut comparators = vec![Comparator::new(Op::Ge, floor)];
+                comparators.extend(ceiling.map(|ceiling| Comparator::new(Op::Lt, ceiling)));
+                comparators
+            }
+            "^" => {
+                let ceiling = match (partial.major, partial.minor, partial.patch) {
+                    (None, _, _) => None,
+                    (Some(0), Some(0), Some(patch)) => patch
+                        .checked_add(1)
+                        .map(|patch| Version::lowest(0, 0, patch)),
+                    (Some(0), Some(minor), _) => minor
+                        .checked_add(1)
+                        .map(|minor| Version::lowest(0, minor, 0)),
+                    (Some(major), _, _) => major
+                        .checked_add(1)
+                        .map(|major| Version::lowest(major, 0, 0)),
+                };
+                let mut comparators = vec![Comparator::new(Op::Ge, floor)];
+                comparators.extend(ceiling.map(|ceiling| Comparator::new(Op::Lt, ceiling)));
+                comparators
+            }
+            ">" => match (partial.major, partial.patch, partial.ceiling()) {
+                // >* matches nothing
+                (None, _, _) => vec![Comparator::new(Op::Lt, Version::lowest(0, 0, 0))],
+                (_, Some(_), _) => vec![Comparator::new(Op::Gt, floor)],
+                (_, None, Some(ceiling)) => {
+                    vec![Comparator::new(
+                        Op::Ge,
+                        Version::new(ceiling.major, ceiling.minor, 0),
+                    )]
+                }
+                // nothing is above the highest version.
+                (_, None, None) => vec![Comparator::new(Op::Lt, Version::lowest(0, 0, 0))],
+            },
+            ">=" => match partial.major {
+                None => Vec::new(),
+                Some(_) => vec![Comparator::new(Op::Ge, floor)],
+            },
+            "<" => match partial.major {
+                None => vec![Comparator::new(Op::Lt, Version::lowest(0, 0, 0))],
+                Some(_) if partial.patch.is_some() => vec![Comparator::new(Op::Lt, floor)],
+                Some(_) => vec![Comparator::new(
+                    Op::Lt,
+                    Version::lowest(floor.major, floor.minor, floor.patch),
+                )],
+            },
+            "<=" => match (partial.patch, partial.ceiling()) {
+                (Some(_), _) => vec![Comparator::new(Op::Le, floor)],
+                (None, Some(ceiling)) => vec![Comparator::new(Op::Lt, ceiling)],
+                (None, None) => Vec::new(),
+            },
+            _ => return Err(invalid(token)),
+        };
+        Ok(comparators)
+    }
+}
+
+/// Returns the range of a dependency spec, e.g. `^1.0.0` or `npm:other@^1.0.0`.
+/// Tags, git, file and url specs have no range.
+pub(crate) fn spec_range(spec: &str) -> Option<VersionRange> {
+    let spec = match spec.strip_prefix("npm:") {
+        Some(alias) => alias.rsplit_once('@').map_or("", |(_, range)| range),
+        None => spec,
+    };
+    if spec.contains([':', '/']) {
+        return None;
+    }
+    spec.parse().ok()
+}
+
+impl FromStr for VersionRange {
+    type Err = PackageLockJsonError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let sets = value
+            .split("||")
+            .map(Self::parse_set)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            raw: value.to_string(),
+            sets,
+        })
+    }
+}
+
+impl fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn satisfies(range: &str, version: &str) -> bool {
+        let range = range.parse::<VersionRange>().unwrap();
+        range.satisfies(&version.parse().unwrap())
+    }
+
+    #[test]
+    fn version_ordering_works() {
+        let mut versions = [
+            "1.0.0",
+            "1.0.0-beta.2",
+            "1.0.0-alpha",
+            "1.0.0-beta.11",
+            "0.9.9",
+        ]
+        .iter()
+        .map(|version| version.parse::<Version>().unwrap())
+        .collect::<Vec<_>>();
+        versions.sort();
+        let versions = versions.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            versions,
+            [
+                "0.9.9",
+                "1.0.0-alpha",
+                "1.0.0-beta.2",
+                "1.0.0-beta.11",
+                "1.0.0"
+            ]
+        );
+        assert!("1.2".parse::<Version>().is_err());
+        let version = "v1.2.3+build.1".parse::<Version>().unwrap();
+        assert_eq!(version.build, ["build", "1"]);
+        assert_eq!(version, Version::new(1, 2, 3));
+        assert_eq!(version.cmp(&Version::new(1, 2, 3)), Ordering::Equal);
+        let versions = ["1.2.3+a", "1.2.3+b", "1.2.3"]
+            .iter()
+            .map(|version| version.parse::<Version>().unwrap())
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(versions.len(), 1);
+    }
+
+    #[test]
+    fn ranges_work() {
+        let cases = [
+            ("^1.2.3", "1.9.0", true),
+            ("^1.2.3", "2.0.0", false),
+            ("^0.2.3", "0.3.0", false),
+            ("^0.0.3", "0.0.4", false),
+            ("~1.2.3", "1.2.9", true),
+            ("~1.2.3", "1.3.0", false),
+            ("~1", "1.9.9", true),
+            ("1.x", "1.4.0", true),
+            ("1.2", "1.3.0", false),
+            ("*", "3.0.0", true),
+            ("", "3.0.0", true),
+            (">= 14", "16.0.0", true),
+            (">1.2", "1.2.9", false),
+            (">1.2", "1.3.0", true),
+            ("<=1.2", "1.2.9", true),
+            ("<1.2", "1.2.0", false),
+            ("1.2.3 - 2.3", "2.3.9", true),
+            ("1.2.3 - 2.3.4", "2.3.5", false),
+            ("^14.17.0 || ^16.13.0 || >=18.0.0", "16.14.0", true),
+            ("^14.17.0 || ^16.13.0 || >=18.0.0", "15.0.0", false),
+            (">=1.0.0 <2.0.0", "2.0.0-beta", false),
+            ("^1.0.0", "1.5.0-beta", false),
+            ("^1.5.0-beta", "1.5.0-rc", true),
+            ("^1.5.0-beta", "1.6.0-rc", false),
+            ("=1.2.3", "1.2.3", true),
+            ("v1.2.3", "1.2.3", true),
+            ("~>1.2", "1.2.5", true),
+            // no upper bound past the highest version.
+            (
+                "^18446744073709551615.0.0",
+                "18446744073709551615.1.0",
+                true,
+            ),
+            (
+                "^0.18446744073709551615.0",
+                "0.18446744073709551615.1",
+                true,
+            ),
+            (
+                "^0.0.18446744073709551615",
+                "0.0.18446744073709551615",
+                true,
+            ),
+            ("~1.18446744073709551615", "1.18446744073709551615.3", true),
+            ("18446744073709551615", "18446744073709551615.2.0", true),
+            ("18446744073709551615", "1.0.0", false),
+            (">18446744073709551615", "18446744073709551615.2.0", false),
+            ("<=18446744073709551615", "18446744073709551615.2.0", true),
+        ];
+        for (range, version, expected) in cases {
+            assert_eq!(satisfies(range, version), expected, "{range} {version}");
+        }
+        assert!("not a range".parse::<VersionRange>().is_err());
+    }
+
+    #[test]
+    fn spec_range_works() {
+        assert!(spec_range("^1.0.0").is_some());
+        assert!(spec_range("npm:string-width@^4.2.0").is_some());
+        assert!(spec_range("file:libs/a").is_none());
+        assert!(spec_range("github:user/repo").is_none());
+        assert!(spec_range("latest").is_none());
+    }
+
+    #[test]
+    fn max_satisfying_works() {
+        let versions = ["1.0.0", "1.5.0", "2.0.0"]
+            .iter()
+            .map(|version| version.parse::<Version>().unwrap())
+            .collect::<Vec<_>>();
+        let range = "^1.0.0".parse::<VersionRange>().unwrap();
+        assert_eq!(range.max_satisfying(&versions), Some(&versions[1]));
+    }
+}
@@ -0,0 +1,418 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graph, hoist,
+    name::{folder_name, is_project_path},
+    registry::{Packument, PackumentSource, PackumentVersion},
+    semver::spec_range,
+    Map, PackageLockJson, PackageLockJsonError, V2Dependency, Version,
+};
+
+/// Change of the package installed at a path.
+#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PackageChange {
+    pub path: String,
+    /// Version before, `None` if the package is added.
+    pub from: Option<String>,
+    /// Version after, `None` if the package is removed.
+    pub to: Option<String>,
+}
+
+/// Lock file with a package upgraded and what changed to get there.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct UpgradePlan {
+    /// Changes sorted by path.
+    pub changes: Vec<PackageChange>,
+    pub lock_file: PackageLockJson,
+}
+
+impl PackageLockJson {
+    /// Returns the installed packages that differ in `other`, sorted by path.
+    pub fn diff(&self, other: &Self) -> Vec<PackageChange> {
+        let (before, after) = (self.v2_packages(), other.v2_packages());
+        let paths = before
+            .keys()
+            .chain(after.keys())
+            .filter(|path| !path.is_empty())
+            .collect::<BTreeSet<_>>();
+        paths
+            .into_iter()
+            .filter_map(|path| {
+                let from = before.get(path).map(|package| package.version.clone());
+                let to = after.get(path).map(|package| package.version.clone());
+                (from != to).then(|| PackageChange {
+                    path: path.clone(),
+                    from,
+                    to,
+                })
+            })
+            .collect()
+    }
+
+    /// Plans the upgrade of every installed copy of `name` to `version`
+    /// using the packuments of `registry`, without network access.
+    ///
+    /// Dependents whose range doesn't accept the new version move to
+    /// their lowest newer version that does, and so on up to the root project,
+    /// whose declared ranges are never changed. Dependencies the new versions
+    /// need and don't find installed in range are added or upgraded
+    /// to the highest version in range, nested in the package needing them.
+    pub fn plan_upgrade(
+        &self,
+        name: &str,
+        version: &str,
+        registry: &impl PackumentSource,
+    ) -> Result<UpgradePlan, PackageLockJsonError> {
+        let packages = self.v2_packages();
+        let target = version.parse::<Version>()?;
+        let mut dependents = Map::<&str, Vec<(&str, &str)>>::new();
+        for path in packages.keys() {
+            for edge in graph::edges(&packages, path) {
+                if let Some(target) = edge.path {
+                    dependents
+                        .entry(target)
+                        .or_default()
+                        .push((path, edge.name));
+                }
+            }
+        }
+        let mut pending = packages
+            .iter()
+            .filter(|(path, package)| !package.link && package_name(path, package) == name)
+            .map(|(path, _)| (path.clone(), name.to_string(), target.clone()))
+            .collect::<Vec<_>>();
+        if pending.is_empty() {
+            return Err(PackageLockJsonError::PackageNotFoundError(name.to_string()));
+        }
+
+        let mut packuments = Map::new();
+        let mut changes = Map::<String, PackumentVersion>::new();
+        while let Some((path, name, version)) = pending.pop() {
+            let packument = load_packument(registry, &mut packuments, &name)?;
+            let manifest = packument
+                .versions
+                .values()
+                .find(|manifest| {
+                    manifest
+                        .version
+                        .parse::<Version>()
+                        .is_ok_and(|candidate| candidate == version)
+                })
+                .cloned()
+                .ok_or_else(|| upgrade_error(format!("{name}@{version} is not published")))?;
+            if let Some(previous) = changes.get(&path) {
+                if previous.version == manifest.version {
+                    continue;
+                }
+                return Err(upgrade_error(format!(
+                    "{path} needs both {} and {}",
+                    previous.version, manifest.version
+                )));
+            }
+            changes.insert(path.clone(), manifest.clone());
+
+            // the packages depending on it have to accept the new version.
+            for &(dependent, dependency_name) in dependents.get(path.as_str()).into_iter().flatten()
+            {
+                let package = &packages[dependent];
+                let spec = match changes.get(dependent) {
+                    Some(manifest) => manifest_spec(manifest, dependency_name),
+                    None => package_spec(package, dependency_name),
+                };
+                if spec.is_none_or(|spec| accepts(spec, &version)) {
+                    continue;
+                }
+                let spec = spec.unwrap_or_default();
+                if is_project_path(dependent) {
+                    return Err(upgrade_error(format!(
+                        "{} requires {dependency_name}@{spec}",
+                        package.name.as_deref().unwrap_or("the root project")
+                    )));
+                }
+                let dependent_name = package_name(dependent, package);
+                let current = changes
+                    .get(dependent)
+                    .map_or(&package.version, |manifest| &manifest.version)
+                    .parse::<Version>()?;
+                let packument = load_packument(registry, &mut packuments, dependent_name)?;
+                let next = packument
+                    .sorted_versions()
+                    .into_iter()
+                    .filter(|(candidate, _)| *candidate > current && !candidate.is_prerelease())
+                    .find(|(_, manifest)| {
+                        manifest_spec(manifest, dependency_name)
+                            .is_none_or(|spec| accepts(spec, &version))
+                    })
+                    .map(|(candidate, _)| candidate)
+                    .ok_or_else(|| {
+                        upgrade_error(format!(
+                            "no version of {dependent_name} accepts {dependency_name}@{version}"
+                        ))
+                    })?;
+                pending.push((dependent.to_string(), dependent_name.to_string(), next));
+            }
+
+            // and its own dependencies have to be installed in range.
+            let required = [
+                (&manifest.dependencies, false),
+                (&manifest.optional_dependencies, true),
+                (&manifest.peer_dependencies, true),
+            ];
+            for (dependencies, can_skip) in required {
+                for (dependency_name, spec) in dependencies.iter().flatten() {
+                    let Some(range) = spec_range(spec) else {
+                        continue;
+                    };
+                    let (dependency_path, current) =
+                        match graph::resolve(&packages, &path, dependency_name) {
+                            Some(resolved) => {
+                                let current = changes
+                                    .get(resolved)
+                                    .map_or(&packages[resolved].version, |manifest| {
+                                        &manifest.version
+                                    });
+                                (resolved.to_string(), current.parse().ok())
+                            }
+                            None if can_skip => continue,
+                            None => (format!("{path}/node_modules/{dependency_name}"), None),
+                        };
+                    if current.is_some_and(|current| range.satisfies(&current)) {
+                        continue;
+                    }
+                    let real_name = spec
+                        .strip_prefix("npm:")
+                        .and_then(|alias| alias.rsplit_once('@'))
+                        .map_or(dependency_name.as_str(), |(real_name, _)| real_name);
+                    let packument = load_packument(registry, &mut packuments, real_name)?;
+                    let versions = packument.sorted_versions();
+                    let next = range
+                        .max_satisfying(versions.iter().map(|(version, _)| version))
+                        .cloned()
+                        .ok_or_else(|| {
+                            upgrade_error(format!("no version of {real_name} in range {spec}"))
+                        })?;
+                    pending.push((dependency_path, real_name.to_string(), next));
+                }
+            }
+        }
+
+        let mut lock_file = self.clone();
+        let mut paths = changes.keys().collect::<Vec<_>>();
+        // parents first.
+        paths.sort();
+        for path in paths {
+            let manifest = &changes[path];
+            let package = lock_file.package(path).cloned().unwrap_or_default();
+            let dist = manifest.dist.as_ref();
+            let package = V2Dependency {
+                version: manifest.version.clone(),
+                resolved: dist.map(|dist| dist.tarball.clone()),
+                integrity: dist.and_then(|dist| dist.integrity.clone()),
+                dependencies: manifest.dependencies.clone(),
+                optional_dependencies: manifest.optional_dependencies.clone(),
+                peer_dependencies: manifest.peer_dependencies.clone(),
+                deprecated: manifest.deprecated.clone(),
+                has_install_script: manifest.has_install_script,
+                ..package
+            };
+            lock_file.insert_package(path.clone(), package)?;
+        }
+        // the flags follow from the new tree, for the added packages and the others.
+        let before = lock_file.v2_packages();
+        let mut planned = before.clone();
+        hoist::set_flags(&mut planned);
+        for (path, package) in planned {
+            let changed = before.get(&path).is_some_and(|previous| {
+                (
+                    previous.is_dev,
+                    previous.is_optional,
+                    previous.is_dev_optional,
+                ) != (package.is_dev, package.is_optional, package.is_dev_optional)
+            });
+            if changed && !path.is_empty() {
+                lock_file.insert_package(path, package)?;
+            }
+        }
+        Ok(UpgradePlan {
+            changes: self.diff(&lock_file),
+            lock_file,
+        })
+    }
+}
+
+fn upgrade_error(message: String) -> PackageLockJsonError {
+    PackageLockJsonError::UpgradeError(message)
+}
+
+/// Returns the name of the package installed at `path`, the real one for aliases.
+fn package_name<'a>(path: &'a str, package: &'a V2Dependency) -> &'a str {
+    package
+        .name
+        .as_deref()
+        .or_else(|| folder_name(path))
+        .unwrap_or(path)
+}
+
+fn load_packument<'a>(
+    registry: &impl PackumentSource,
+    packuments: &'a mut Map<String, Packument>,
+    name: &str,
+) -> Result<&'a Packument, PackageLockJsonError> {
+    if !packuments.contains_key(name) {
+        let packument = registry
+            .packument(name)?
+            .ok_or_else(|| PackageLockJsonError::PackageNotFoundError(name.to_string()))?;
+        packuments.insert(name.to_string(), packument);
+    }
+    Ok(&packuments[name])
+}
+
+fn accepts(spec: &str, version: &Version) -> bool {
+    spec_range(spec).is_none_or(|range| range.satisfies(version))
+}
+
+fn manifest_spec<'a>(manifest: &'a PackumentVersion, name: &str) -> Option<&'a str> {
+    [
+        &manifest.dependencies,
+        &manifest.optional_dependencies,
+        &manifest.peer_dependencies,
+    ]
+    .into_iter()
+    .flatten()
+    .find_map(|dependencies| dependencies.get(name))
+    .map(String::as_str)
+}
+
+fn package_spec<'a>(package: &'a V2Dependency, name: &str) -> Option<&'a str> {
+    [
+        &package.dependencies,
+        &package.optional_dependencies,
+        &package.peer_dependencies,
+        &package.dev_dependencies,
+    ]
+    .into_iter()
+    .flatten()
+    .find_map(|dependencies| dependencies.get(name))
+    .map(String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{parse, registry::PackumentDir, remove_entry};
+
+    fn lock_file() -> PackageLockJson {
+        let content = std::fs::read_to_string("tests/upgrade/package-lock.json").unwrap();
+        parse(content).unwrap()
+    }
+
+    #[test]
+    fn plan_upgrade_works() {
+        let lock_file = lock_file();
+        let registry = PackumentDir::new("tests/registry");
+        let plan = lock_file
+            .plan_upgrade("@scope/c", "2.0.0", &registry)
+            .unwrap();
+        let change = |path: &str, from: Option<&str>, to: &str| PackageChange {
+            path: path.to_string(),
+            from: from.map(ToString::to_string),
+            to: Some(to.to_string()),
+        };
+        assert_eq!(
+            plan.changes,
+            [
+                change("node_modules/@scope/c", Some("1.0.0"), "2.0.0"),
+                change("node_modules/a", Some("1.0.0"), "1.2.0"),
+                change("node_modules/a/node_modules/d", None, "1.0.1"),
+                // the lowest version accepting both.
+                change("node_modules/b", Some("1.0.0"), "1.5.0"),
+            ]
+        );
+
+        let a = plan.lock_file.package("node_modules/a").unwrap();
+        assert_eq!(
+            a.resolved.as_deref(),
+            Some("https://registry.npmjs.org/a/-/a-1.2.0.tgz")
+        );
+        assert_eq!(a.integrity.as_deref(), Some("sha512-a120"));
+        assert_eq!(a.dependencies.as_ref().unwrap()["d"], "^1.0.0");
+        assert_eq!(plan.lock_file.diff(&lock_file).len(), 4);
+        assert!(lock_file.diff(&lock_file).is_empty());
+    }
+
+    #[test]
+    fn plan_upgrade_fails_out_of_range() {
+        let lock_file = lock_file();
+        let registry = PackumentDir::new("tests/registry");
+        let err = lock_file.plan_upgrade("@scope/c", "3.0.0-beta.1", &registry);
+        assert!(matches!(err, Err(PackageLockJsonError::UpgradeError(_))));
+        let err = lock_file.plan_upgrade("@scope/c", "9.9.9", &registry);
+        assert!(matches!(err, Err(PackageLockJsonError::UpgradeError(_))));
+        let err = lock_file.plan_upgrade("missing", "1.0.0", &registry);
+        assert!(matches!(
+            err,
+            Err(PackageLockJsonError::PackageNotFoundError(_))
+        ));
+    }
+
+    #[test]
+    fn plan_upgrade_sets_flags() {
+        let mut lock_file = lock_file();
+        let packages = lock_file.packages_by_path.as_mut().unwrap();
+        let root = packages.get_mut("").unwrap();
+        let a = remove_entry(root.dependencies.as_mut().unwrap(), "a").unwrap();
+        root.dev_dependencies = Some(Map::from([("a".to_string(), a)]));
+        packages.get_mut("node_modules/a").unwrap().is_dev = true;
+
+        let registry = PackumentDir::new("tests/registry");
+        let plan = lock_file
+            .plan_upgrade("@scope/c", "2.0.0", &registry)
+            .unwrap();
+        let packages = plan.lock_file.packages_by_path.as_ref().unwrap();
+        assert!(packages["node_modules/a/node_modules/d"].is_dev);
+        assert!(!packages["node_modules/@scope/c"].is_dev);
+        let mut expected = packages.clone();
+        hoist::set_flags(&mut expected);
+        assert_eq!(*packages, expected);
+    }
+
+    #[test]
+    fn plan_upgrade_keeps_workspace_flags() {
+        // `a` is a dependency of the workspace `w` only.
+        let mut lock_file = lock_file();
+        let packages = lock_file.packages_by_path.as_mut().unwrap();
+        let root = packages.get_mut("").unwrap();
+        let a = remove_entry(root.dependencies.as_mut().unwrap(), "a").unwrap();
+        root.workspaces = Some(vec!["packages/w".to_string()]);
+        let workspace = V2Dependency {
+            name: Some("w".to_string()),
+            version: "1.0.0".to_string(),
+            dependencies: Some(Map::from([("a".to_string(), a)])),
+            ..V2Dependency::default()
+        };
+        let link = V2Dependency {
+            resolved: Some("packages/w".to_string()),
+            link: true,
+            ..V2Dependency::default()
+        };
+        packages.insert("packages/w".to_string(), workspace);
+        packages.insert("node_modules/w".to_string(), link);
+
+        let registry = PackumentDir::new("tests/registry");
+        let plan = lock_file
+            .plan_upgrade("@scope/c", "2.0.0", &registry)
+            .unwrap();
+        let packages = plan.lock_file.packages_by_path.as_ref().unwrap();
+        for (path, package) in packages {
+            assert!(
+                !package.is_dev && !package.is_optional && !package.is_dev_optional,
+                "{path}"
+            );
+        }
+        assert!(packages.contains_key("node_modules/w"));
+        assert_eq!(packages["node_modules/@scope/c"].version, "2.0.0");
+    }
+}